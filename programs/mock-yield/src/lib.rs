@@ -0,0 +1,89 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::{self, Mint, Token, TokenAccount, Transfer};
+
+declare_id!("FJu8zottVv87JmKeMAc4CbV2rLbxs6XA6wpjBctg1Z9U");
+
+// Seed prefix of a depositor's pool token account, followed by the depositor key
+pub const POOL_SEED: &[u8] = b"pool";
+
+// A bare-bones lending vault for exercising split-funds' yield CPI in tests. Each
+// depositor has a pool token account; interest is simulated by minting straight into it,
+// and a withdrawal returns everything the pool holds.
+#[program]
+pub mod mock_yield {
+    use super::*;
+
+    // Creates the pool token account of a depositor
+    pub fn init_pool(_ctx: Context<InitPool>) -> Result<()> {
+        Ok(())
+    }
+
+    // Moves `amount` from the depositor's token account into their pool
+    pub fn deposit(ctx: Context<Deposit>, amount: u64) -> Result<()> {
+        let cpi_accounts = Transfer {
+            from: ctx.accounts.from.to_account_info(),
+            to: ctx.accounts.pool.to_account_info(),
+            authority: ctx.accounts.depositor.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new(ctx.accounts.token_program.to_account_info(), cpi_accounts);
+        token::transfer(cpi_ctx, amount)
+    }
+
+    // Returns the whole pool, principal and interest, to the depositor's token account
+    pub fn withdraw(ctx: Context<Withdraw>) -> Result<()> {
+        let depositor = ctx.accounts.depositor.key();
+        let bump = [ctx.bumps.pool];
+        let seeds: &[&[u8]] = &[POOL_SEED, depositor.as_ref(), &bump];
+        let signer = &[seeds];
+        let cpi_accounts = Transfer {
+            from: ctx.accounts.pool.to_account_info(),
+            to: ctx.accounts.to.to_account_info(),
+            authority: ctx.accounts.pool.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new_with_signer(ctx.accounts.token_program.to_account_info(), cpi_accounts, signer);
+        token::transfer(cpi_ctx, ctx.accounts.pool.amount)
+    }
+}
+
+// Context for creating a depositor's pool
+#[derive(Accounts)]
+pub struct InitPool<'info> {
+    /// CHECK: only used as a seed; any account may own a pool
+    pub depositor: UncheckedAccount<'info>,
+    pub mint: Account<'info, Mint>,
+    #[account(
+        init,
+        payer = payer,
+        seeds = [POOL_SEED, depositor.key().as_ref()],
+        bump,
+        token::mint = mint,
+        token::authority = pool
+    )]
+    pub pool: Account<'info, TokenAccount>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+// Context for depositing into a pool
+#[derive(Accounts)]
+pub struct Deposit<'info> {
+    pub depositor: Signer<'info>,
+    #[account(mut, token::authority = depositor)]
+    pub from: Account<'info, TokenAccount>,
+    #[account(mut, seeds = [POOL_SEED, depositor.key().as_ref()], bump)]
+    pub pool: Account<'info, TokenAccount>,
+    pub token_program: Program<'info, Token>,
+}
+
+// Context for withdrawing a pool
+#[derive(Accounts)]
+pub struct Withdraw<'info> {
+    pub depositor: Signer<'info>,
+    #[account(mut, seeds = [POOL_SEED, depositor.key().as_ref()], bump)]
+    pub pool: Account<'info, TokenAccount>,
+    #[account(mut, token::authority = depositor)]
+    pub to: Account<'info, TokenAccount>,
+    pub token_program: Program<'info, Token>,
+}
@@ -1,168 +1,3309 @@
 use anchor_lang::prelude::*;
-use anchor_spl::token::{self, Mint, Token, TokenAccount, Transfer};
+use anchor_lang::solana_program::instruction::{AccountMeta, Instruction};
+use anchor_lang::solana_program::program::invoke_signed;
+use anchor_lang::solana_program::pubkey::MAX_SEED_LEN;
+use anchor_lang::system_program;
+use anchor_spl::token::{self, CloseAccount, Mint, Token, TokenAccount, TransferChecked};
 
 declare_id!("2JiRP2mrVXWBshpkif8F9e5vrvnHtJWUt5WUiyEftJKN");
 
+// Longest group name, in bytes, that fits in a GroupAccount
+pub const MAX_NAME_LEN: usize = 64;
+
+// Highest total_cost a group may be created with, in base units of the mint or lamports.
+// Keeps fee and share math far from overflow; tune as needed.
+pub const MAX_TOTAL_COST: u64 = 1_000_000_000_000_000;
+
+// Basis points in 100%
+pub const BPS_DENOMINATOR: u64 = 10_000;
+
+// Seconds after the grace period ends before the owner may sweep an underfunded escrow
+pub const EMERGENCY_DELAY: i64 = 7 * 24 * 60 * 60;
+
+// Furthest a single extend_due call can push the due time, in seconds
+pub const MAX_DUE_EXTENSION: i64 = 90 * 24 * 60 * 60;
+
+// Longest settlement delay a group can put between its grace period and payout, in seconds
+pub const MAX_SETTLEMENT_DELAY: i64 = 7 * 24 * 60 * 60;
+
+// Furthest ahead of now a group's due time can be set, in seconds
+pub const MAX_DUE_AHEAD: i64 = 5 * 365 * 24 * 60 * 60;
+
+// Seed prefix of a group, followed by the owner key, the group name and the owner's nonce
+pub const GROUP_SEED: &[u8] = b"group";
+
+// Seed prefix of an owner's OwnerCounter, followed by the owner key
+pub const OWNER_COUNTER_SEED: &[u8] = b"owner-counter";
+
+// Seed of the program-wide ProgramConfig account
+pub const CONFIG_SEED: &[u8] = b"config";
+
+// Seed prefix of a token escrow's vault, followed by the group and mint keys
+pub const VAULT_SEED: &[u8] = b"vault";
+
+// Most members a group keeps in its on-chain member list
+pub const MAX_LISTED_MEMBERS: usize = 32;
+
+// Most members batch_invite adds in one call, keeping it within compute and size limits
+pub const MAX_BATCH_INVITE: usize = 10;
+
+// Most members refund_all refunds in one call; larger groups are refunded over several calls
+pub const MAX_REFUND_BATCH: usize = 8;
+
+// Most mints a group can restrict its escrows to
+pub const MAX_ALLOWED_MINTS: usize = 4;
+
+// Most wallets that can approve a group's payouts; approvals are tracked as bits of a u8
+pub const MAX_APPROVERS: usize = 8;
+
+// Most lending programs the config admin can approve for yield
+pub const MAX_YIELD_PROGRAMS: usize = 4;
+
+// Length of the display symbol stored on a group, padded with zero bytes
+pub const SYMBOL_LEN: usize = 8;
+
+// Seed of the TestClock account in builds with the test-clock feature
+#[cfg(feature = "test-clock")]
+pub const TEST_CLOCK_SEED: &[u8] = b"test-clock";
+
+// Most co-recipients a payout can be split between; larger lists are paid with
+// execute_payout_page
+pub const MAX_RECIPIENTS: usize = 16;
+
 #[program]
 pub mod split_funds {
     use super::*;
 
-     // Creates a new group for subscription splitting
-    pub fn create_group(
-        ctx: Context<CreateGroup>,
-        group_name: String,
-        total_cost: u64,
-        subscription_due: i64,
-    ) -> Result<()> {
+    // Sets the time read by `now` when the TestClock account is passed along; test
+    // builds only
+    #[cfg(feature = "test-clock")]
+    pub fn set_test_clock(ctx: Context<SetTestClock>, unix_timestamp: i64) -> Result<()> {
+        ctx.accounts.test_clock.unix_timestamp = unix_timestamp;
+        Ok(())
+    }
+
+    // Creates the program-wide config once; the signer becomes its admin and must be the
+    // program's upgrade authority, so nobody can claim the config ahead of the deployer.
+    // Until it exists, groups get no default fee and only the built-in MAX_TOTAL_COST cap.
+    pub fn initialize_config(ctx: Context<InitializeConfig>, params: ConfigParams) -> Result<()> {
+        params.validate()?;
+        let config = &mut ctx.accounts.config;
+        config.admin = ctx.accounts.admin.key();
+        config.default_fee_bps = params.default_fee_bps;
+        config.max_total_cost = params.max_total_cost;
+        config.anti_self_split = params.anti_self_split;
+        config.bump = ctx.bumps.config;
+        Ok(())
+    }
+
+    // Lets the admin change the program-wide defaults and caps; existing groups keep the
+    // fee and anti-self-split policy they were created with
+    pub fn update_config(ctx: Context<UpdateConfig>, params: ConfigParams) -> Result<()> {
+        params.validate()?;
+        let config = &mut ctx.accounts.config;
+        config.default_fee_bps = params.default_fee_bps;
+        config.max_total_cost = params.max_total_cost;
+        config.anti_self_split = params.anti_self_split;
+        Ok(())
+    }
+
+    // Lets the config admin halt (or resume) deposits, invites and new groups across the
+    // whole program, e.g. while a vulnerability is fixed. Refunds and payouts keep working
+    // so members can always get their money out.
+    pub fn set_program_paused(ctx: Context<UpdateConfig>, paused: bool) -> Result<()> {
+        ctx.accounts.config.paused = paused;
+        Ok(())
+    }
+
+    // Replaces the lending programs groups may put escrowed tokens in. Escrows sign for
+    // these programs, so only vetted ones belong here. Removing a program stops new
+    // deposits into it; lent funds can still be brought back.
+    pub fn set_yield_programs(ctx: Context<UpdateConfig>, programs: Vec<Pubkey>) -> Result<()> {
+        require!(programs.len() <= MAX_YIELD_PROGRAMS, CustomError::InvalidYieldConfig);
+        require!(programs.iter().all(can_sign_for), CustomError::InvalidYieldConfig);
+        ctx.accounts.config.yield_programs = programs;
+        Ok(())
+    }
+
+    // Creates a new group for subscription splitting. A `fee_bps` of None takes the
+    // program config's default fee. `nonce` must be the owner's next nonce from their
+    // OwnerCounter (0 for a first group), so one owner can have several groups of one name.
+    pub fn create_group(ctx: Context<CreateGroup>, params: CreateGroupParams) -> Result<()> {
+        let counter = &mut ctx.accounts.owner_counter;
+        require!(params.nonce == counter.next_nonce, CustomError::WrongNonce);
+        counter.owner = ctx.accounts.owner.key();
+        counter.next_nonce = counter.next_nonce.checked_add(1).ok_or(CustomError::Overflow)?;
+        counter.bump = ctx.bumps.owner_counter;
+
+        let CreateGroupParams {
+            group_name,
+            total_cost,
+            subscription_due,
+            is_native,
+            grace_period,
+            allow_partial,
+            fee_bps,
+            fee_recipient,
+            min_members,
+            max_members,
+            recipients,
+            payout_recipient,
+            late_fee_bps,
+            invite_only,
+            max_per_member,
+            allow_late_join,
+            stream_payouts,
+            min_deposit,
+            category,
+            yield_enabled,
+            yield_program,
+            allowed_mints,
+            allow_credit,
+            restrict_payout,
+            symbol,
+            decimals_hint,
+            approvers,
+            threshold,
+            approval_above,
+            settlement_delay,
+            nonce,
+            refund_penalty_bps,
+            deposit_deadline,
+            reject_empty_payout,
+        } = params;
+        let config = load_config(&ctx.accounts.config)?;
+        require!(!config.paused, CustomError::ProgramPaused);
+        let fee_bps = fee_bps.unwrap_or(config.default_fee_bps);
+
+        let (now, _) = now(ctx.remaining_accounts)?;
+        let group_name = validate_name(&group_name)?.to_string();
+        require!(total_cost > 0, CustomError::ZeroCost);
+        require!(total_cost <= config.cost_cap(), CustomError::CostTooHigh);
+        require!(grace_period >= 0, CustomError::InvalidGracePeriod);
+        // grace_end() relies on the due time plus the grace period fitting in an i64
+        validate_due(subscription_due, grace_period, now)?;
+        require!(subscription_due > now, CustomError::DueInPast);
+        require!(
+            deposit_deadline == 0 || (now < deposit_deadline && deposit_deadline <= subscription_due),
+            CustomError::InvalidDepositDeadline
+        );
+        require!(
+            (0..=MAX_SETTLEMENT_DELAY).contains(&settlement_delay),
+            CustomError::InvalidSettlementDelay
+        );
+        require!(u64::from(fee_bps) <= BPS_DENOMINATOR, CustomError::InvalidFee);
+        require!(u64::from(late_fee_bps) <= BPS_DENOMINATOR, CustomError::InvalidFee);
+        // Penalties kept by a native escrow would have no way out if the group were cancelled
+        require!(
+            u64::from(refund_penalty_bps) <= BPS_DENOMINATOR && (!is_native || refund_penalty_bps == 0),
+            CustomError::InvalidRefundPenalty
+        );
+        require!(max_members > 0 && min_members <= max_members, CustomError::InvalidMemberBounds);
+        // Under the anti-self-split policy a group can't be one wallet paying itself
+        require!(!config.anti_self_split || min_members >= 2, CustomError::SelfSplit);
+        validate_recipients(&recipients)?;
+        validate_approvers(&approvers, threshold)?;
+        require!(category < GroupCategory::COUNT, CustomError::InvalidCategory);
+        // Streamed deposits go to the payout recipient whole, with nothing to split
+        require!(
+            !stream_payouts || (!is_native && fee_bps == 0 && recipients.is_empty() && !allow_credit),
+            CustomError::InvalidStreamConfig
+        );
+        // The escrow signs for the yield program, so it must be one the config admin vetted
+        require!(
+            !yield_enabled || (!is_native && !stream_payouts && can_sign_for(&yield_program)),
+            CustomError::InvalidYieldConfig
+        );
+        require!(
+            !yield_enabled || config.yield_programs.contains(&yield_program),
+            CustomError::YieldProgramNotAllowed
+        );
+
+        // Token groups name their mint; native SOL groups must not pass one
+        let mint = match (&ctx.accounts.mint, is_native) {
+            (Some(mint), false) => mint.key(),
+            (None, true) => Pubkey::default(),
+            _ => return err!(CustomError::WrongAssetType),
+        };
+        // An allowlist names every mint the group takes, starting with its primary one
+        require!(allowed_mints.len() <= MAX_ALLOWED_MINTS, CustomError::TooManyMints);
+        require!(
+            allowed_mints.is_empty() || (!is_native && allowed_mints.contains(&mint)),
+            CustomError::MintNotAllowed
+        );
+
         let group = &mut ctx.accounts.group;
         group.owner = *ctx.accounts.owner.key; // Group creator
+        group.mint = mint;                     // Primary token contributions are made in
+        group.is_native = is_native;           // Contributions are in SOL rather than SPL tokens
         group.group_name = group_name;         // Name of the group
         group.total_cost = total_cost;         // Total subscription cost
         group.subscription_due = subscription_due; // Subscription due time (timestamp)
+        group.grace_period = grace_period;     // Seconds late deposits are still accepted
         group.member_count = 0;                // Initialize member count
+        group.total_weight = 0;                // Sum of member weights
+        group.paid_count = 0;                  // No member has paid yet
+        group.allow_partial = allow_partial;   // Whether payout may proceed before everyone paid
         group.is_active = true;                // Mark group as active
+        group.is_cancelled = false;            // Not cancelled
+        group.pending_owner = None;            // No ownership transfer in progress
+        group.is_paused = false;               // Deposits open
+        group.fee_bps = fee_bps;               // Platform fee taken at payout
+        group.fee_recipient = fee_recipient;   // Wallet receiving the platform fee
+        group.cycle_count = 0;                 // No billing cycle paid out yet
+        group.min_members = min_members;       // Members required before payout
+        group.max_members = max_members;       // Seats available in the group
+        group.recipients = recipients;         // Co-owners splitting the payout, if any
+        group.payout_recipient = payout_recipient.unwrap_or(group.owner); // Paid instead of the owner
+        group.members = Vec::new();            // Wallets of invited members
+        group.disputed = false;                // No member is contesting the payout
+        group.paid_out_at = 0;                 // Never paid out
+        group.escrow_count = 0;                // Escrows are created separately
+        group.escrows_paid = 0;                // None paid out this cycle
+        group.late_fee_bps = late_fee_bps;     // Surcharge on deposits after the due time
+        group.bump = ctx.bumps.group;          // Canonical bump of the group PDA
+        group.nonce = nonce;                   // Tells apart the owner's groups of the same name
+        group.invite_only = invite_only;       // Whether joining needs the owner's signature
+        group.max_per_member = max_per_member; // Cap on one member's contribution, 0 for none
+        group.allow_late_join = allow_late_join; // Whether members can join once deposits arrive
+        group.total_held = 0;                  // Nothing deposited yet
+        group.stream_payouts = stream_payouts; // Whether deposits are forwarded as they arrive
+        group.streamed_total = 0;              // Nothing forwarded yet
+        group.min_deposit = min_deposit;       // Smallest installment accepted
+        group.category = category;             // GroupCategory, for discovery
+        group.yield_enabled = yield_enabled;   // Whether idle escrow funds may earn yield
+        group.yield_program = yield_program;   // Program the escrow lends through
+        group.allowed_mints = allowed_mints;   // Mints escrows may be created for; empty for any
+        group.allow_credit = allow_credit;     // Whether overpayments carry over to the next cycle
+        group.restrict_payout = restrict_payout; // Whether only the owner may settle payouts
+        group.symbol = symbol;                 // Display only, e.g. "USD"
+        group.decimals_hint = decimals_hint;   // Display only, decimals to render amounts with
+        group.approvers = approvers;           // Wallets that can approve large payouts
+        group.threshold = threshold;           // Approvals a large payout needs
+        group.approval_above = approval_above; // Escrow balance above which approvals are needed
+        group.settlement_delay = settlement_delay; // Buffer between the grace period and payout
+        group.anti_self_split = config.anti_self_split; // Policy in force when the group was created
+        group.refund_penalty_bps = refund_penalty_bps; // Kept from refunds members ask for before due
+        group.deposit_deadline = deposit_deadline; // Collection cutoff ahead of the due time, 0 for none
+        group.reject_empty_payout = reject_empty_payout; // Whether a cycle nobody paid into fails to settle
+        group.surcharge = 0;                   // No one-time charges yet
+        group.fees_held = 0;                   // No late surcharges or penalties yet
+        group.payout_in_progress = false;      // No paged payout started yet
+        group.pinned_cost = 0;                 // No shares pinned yet
+        group.pinned_weight = 0;
+        group.pinned_count = 0;
+
+        emit!(GroupCreated {
+            group: group.key(),
+            owner: group.owner,
+            mint: group.mint,
+            total_cost,
+            subscription_due,
+            category,
+            symbol,
+            decimals_hint,
+            timestamp: now,
+        });
         Ok(())
     }
 
-    // Adds a new member to an existing group
-    pub fn invite_member(ctx: Context<InviteMember>) -> Result<()> {
-        let member = &mut ctx.accounts.member;
-        member.group = ctx.accounts.group.key();
-        member.member = *ctx.accounts.member_authority.key;
-        member.contributed = 0;
-        member.has_paid = false; // Mark as not paid
+    // Adds a new member to an existing group. Wallets may join themselves, or be invited by
+    // the owner without signing; invite-only groups always need the owner to sign. `payer`
+    // covers the membership's rent, so an owner can sponsor their members' accounts.
+    // `member_due` gives the member a later personal deadline than the group's, e.g. for
+    // someone joining late; granting one also needs the owner's signature.
+    pub fn invite_member(ctx: Context<InviteMember>, weight: u16, member_due: Option<i64>) -> Result<()> {
+        check_not_paused(&ctx.accounts.config)?;
+        require!(weight > 0, CustomError::InvalidWeight);
+
+        // Paid out and cancelled groups take no new members
+        let group = &mut ctx.accounts.group;
+        require!(group.is_active && !group.is_cancelled, CustomError::InactiveGroup);
+        // Shares depend on who is in the group, so membership is locked once money arrives
+        require!(group.total_held == 0 || group.allow_late_join, CustomError::MembershipLocked);
+        // A newcomer would leave a paged payout short of a paid member, stalling its last pages
+        require!(!group.payout_in_progress, CustomError::PayoutInProgress);
+        let owner_signed = ctx.accounts.owner.as_ref().is_some_and(|owner| owner.key() == group.owner);
+        if group.invite_only || member_due.is_some() {
+            require!(owner_signed, CustomError::Unauthorized);
+        }
+        // Nobody else can sign a wallet up
+        require!(owner_signed || ctx.accounts.member_authority.is_signer, CustomError::Unauthorized);
+        let member_due = member_due.unwrap_or(group.subscription_due);
+        require!(member_due >= group.subscription_due, CustomError::DueNotLater);
+        require!(
+            member_due - group.subscription_due <= MAX_DUE_EXTENSION,
+            CustomError::DueTooFar
+        );
+        grace_end_of(member_due, group.grace_period)?;
+        group.add_member(ctx.accounts.member_authority.key(), weight)?;
+
+        let mut member = MemberAccount::new(group.key(), ctx.accounts.member_authority.key(), weight, member_due);
+        group.pin_late_join(&mut member)?;
+        ctx.accounts.member.set_inner(member);
         Ok(())
     }
 
-    // Allows a member to deposit their share into the escrow account
-    pub fn deposit_funds(ctx: Context<DepositFunds>, amount: u64) -> Result<()> {
-        let member = &mut ctx.accounts.member;
+    // Lets the owner add up to MAX_BATCH_INVITE members, each with weight 1, in one call.
+    // The membership PDA of each wallet in `members` is passed, in the same order, as a
+    // writable remaining account; the owner pays their rent.
+    pub fn batch_invite<'info>(
+        ctx: Context<'_, '_, 'info, 'info, BatchInvite<'info>>,
+        members: Vec<Pubkey>,
+    ) -> Result<()> {
+        check_not_paused(&ctx.accounts.config)?;
+        require!(members.len() <= MAX_BATCH_INVITE, CustomError::BatchTooLarge);
+        require!(ctx.remaining_accounts.len() == members.len(), CustomError::WrongMemberList);
+
+        let group = &mut ctx.accounts.group;
+        require!(group.is_active && !group.is_cancelled, CustomError::InactiveGroup);
+        require!(group.total_held == 0 || group.allow_late_join, CustomError::MembershipLocked);
+        // A newcomer would leave a paged payout short of a paid member, stalling its last pages
+        require!(!group.payout_in_progress, CustomError::PayoutInProgress);
+        let group_key = group.key();
+        let lamports = Rent::get()?.minimum_balance(MemberAccount::SPACE);
+
+        for (i, (wallet, info)) in members.iter().zip(ctx.remaining_accounts).enumerate() {
+            require!(!members[..i].contains(wallet), CustomError::AlreadyMember);
+            let (address, bump) = member_pda(&group_key, wallet);
+            require_keys_eq!(info.key(), address, CustomError::AccountOrderMismatch);
+            require!(info.data_is_empty(), CustomError::AlreadyMember);
+
+            // Create the membership PDA the way invite_member's `init` would
+            let bump = [bump];
+            let seeds: &[&[u8]] = &[group_key.as_ref(), wallet.as_ref(), &bump];
+            create_pda_account(
+                &ctx.accounts.system_program,
+                &ctx.accounts.owner.to_account_info(),
+                info,
+                lamports,
+                MemberAccount::SPACE,
+                seeds,
+            )?;
+
+            group.add_member(*wallet, 1)?;
+            let mut member = MemberAccount::new(group_key, *wallet, 1, group.subscription_due);
+            group.pin_late_join(&mut member)?;
+            member.try_serialize(&mut &mut info.try_borrow_mut_data()?[..])?;
+        }
+        Ok(())
+    }
+
+    // Creates the escrow PDA of one mint the group accepts, along with the token vault it
+    // controls. A group may accept several mints, e.g. USDC and USDT; base units of every
+    // accepted mint count the same toward a member's share.
+    pub fn initialize_escrow(ctx: Context<InitializeEscrow>) -> Result<()> {
+        let vault = ctx.accounts.escrow_token_account.key();
+        let mint = ctx.accounts.mint.key();
+        init_token_escrow(&mut ctx.accounts.group, &mut ctx.accounts.escrow, ctx.bumps.escrow, vault, mint)
+    }
+
+    // Retry-safe initialize_escrow for client flows that may send it twice: creates the
+    // escrow and vault if they don't exist yet, and otherwise only checks that the
+    // existing escrow is this group's escrow of `mint`, leaving it untouched.
+    pub fn initialize_escrow_if_needed(ctx: Context<InitializeEscrowIfNeeded>) -> Result<()> {
+        let vault = ctx.accounts.escrow_token_account.key();
+        let mint = ctx.accounts.mint.key();
+        let escrow = &ctx.accounts.escrow;
+        // A fresh account is all zeroes; anything else was initialized before
+        if escrow.group == Pubkey::default() {
+            return init_token_escrow(&mut ctx.accounts.group, &mut ctx.accounts.escrow, ctx.bumps.escrow, vault, mint);
+        }
+        require_keys_eq!(escrow.group, ctx.accounts.group.key(), CustomError::WrongEscrow);
+        require_keys_eq!(escrow.mint, mint, CustomError::WrongMint);
+        require_keys_eq!(escrow.vault, vault, CustomError::WrongVault);
+        Ok(())
+    }
+
+    // Creates the escrow PDA for a native SOL group; the PDA holds the lamports itself
+    pub fn initialize_sol_escrow(ctx: Context<InitializeSolEscrow>) -> Result<()> {
         let group = &mut ctx.accounts.group;
+        require!(group.is_native, CustomError::WrongAssetType);
+        group.escrow_count = group.escrow_count.checked_add(1).ok_or(CustomError::Overflow)?;
+
+        let escrow = &mut ctx.accounts.escrow;
+        escrow.group = group.key();
+        escrow.total_held = 0;
+        escrow.bump = ctx.bumps.escrow;
+        escrow.vault = Pubkey::default(); // Lamports are held by the escrow PDA itself
+        escrow.mint = group.mint;         // Pubkey::default() for native groups
+        escrow.paid_cycles = group.cycle_count;
+        escrow.late_fees = 0;
+        escrow.payout_account = Pubkey::default(); // Unused by native SOL groups
+        escrow.paid_cursor = 0;
+        escrow.in_yield = 0;
+        escrow.credit_held = 0;
+        escrow.approvals = 0;
+        escrow.penalties = 0;
+        Ok(())
+    }
+
+    // Allows a member to deposit their share into the escrow account. Returns what the
+    // member still owes toward their share.
+    pub fn deposit_funds(ctx: Context<DepositFunds>, amount: u64) -> Result<u64> {
+        deposit_tokens(ctx, amount, [0; 32])
+    }
+
+    // deposit_funds with a reference of the member's choosing, e.g. an invoice id from an
+    // external billing system, carried in the MemberDeposited event for reconciliation
+    pub fn deposit_funds_with_memo(ctx: Context<DepositFunds>, amount: u64, memo: [u8; 32]) -> Result<u64> {
+        deposit_tokens(ctx, amount, memo)
+    }
+
+    // Allows a member of a native SOL group to deposit their share as lamports
+    pub fn deposit_sol(ctx: Context<DepositSol>, amount: u64) -> Result<()> {
+        check_not_paused(&ctx.accounts.config)?;
+        let (now, _) = now(ctx.remaining_accounts)?;
+        require!(ctx.accounts.group.is_native, CustomError::WrongAssetType);
+        record_deposit(&mut ctx.accounts.group, &mut ctx.accounts.member, &mut ctx.accounts.escrow, amount, now)?;
+
+        // Transfer lamports from the member straight into the escrow PDA
+        let cpi_accounts = system_program::Transfer {
+            from: ctx.accounts.member_authority.to_account_info(),
+            to: ctx.accounts.escrow.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new(ctx.accounts.system_program.to_account_info(), cpi_accounts);
+        system_program::transfer(cpi_ctx, amount)?;
+
+        emit!(MemberDeposited {
+            group: ctx.accounts.group.key(),
+            member: ctx.accounts.member.member,
+            amount,
+            total_held: ctx.accounts.escrow.total_held,
+            memo: [0; 32],
+            timestamp: now,
+        });
+        Ok(())
+    }
+
+    // Returns a member's contribution from escrow while the group is still collecting, or
+    // their unspent credit once it has been paid out
+    pub fn refund_member(ctx: Context<RefundMember>) -> Result<()> {
+        let (now, _) = now(ctx.remaining_accounts)?;
+        require!(!ctx.accounts.group.is_native, CustomError::WrongAssetType);
+        let amount = record_refund(&mut ctx.accounts.group, &mut ctx.accounts.member, &mut ctx.accounts.escrow, true, now)?;
+
+        // Transfer SPL tokens from escrow back to the member
+        transfer_from_escrow(
+            &ctx.accounts.token_program,
+            &ctx.accounts.escrow,
+            &ctx.accounts.escrow_token_account,
+            &ctx.accounts.mint,
+            ctx.accounts.member_token_account.to_account_info(),
+            amount,
+        )
+    }
+
+    // Returns a member's lamports from a native SOL group's escrow
+    pub fn refund_member_sol(ctx: Context<RefundMemberSol>) -> Result<()> {
+        let (now, _) = now(ctx.remaining_accounts)?;
+        require!(ctx.accounts.group.is_native, CustomError::WrongAssetType);
+        let amount = record_refund(&mut ctx.accounts.group, &mut ctx.accounts.member, &mut ctx.accounts.escrow, true, now)?;
+
+        // The escrow is program-owned, so lamports are moved directly
+        ctx.accounts.escrow.sub_lamports(amount)?;
+        ctx.accounts.member_authority.add_lamports(amount)?;
+        Ok(())
+    }
+
+    // Lets the owner push refunds to the members of a cancelled group. Each member account
+    // is passed with the token account receiving its refund as a writable pair in
+    // `remaining_accounts`, at most MAX_REFUND_BATCH pairs per call. Members with nothing
+    // to refund are skipped, so a large group is refunded by calling again with the rest.
+    // Refunded members stay in the group, so member_count and total_weight are unchanged.
+    pub fn refund_all<'info>(ctx: Context<'_, '_, 'info, 'info, RefundAll<'info>>) -> Result<()> {
+        require!(!ctx.accounts.group.is_native, CustomError::WrongAssetType);
+        require!(ctx.accounts.group.is_cancelled, CustomError::NotCancelled);
+        require!(ctx.remaining_accounts.len().is_multiple_of(2), CustomError::WrongMemberList);
+        require!(ctx.remaining_accounts.len() / 2 <= MAX_REFUND_BATCH, CustomError::BatchTooLarge);
+
+        let group_key = ctx.accounts.group.key();
+        for pair in ctx.remaining_accounts.chunks(2) {
+            // Each pair is the member account first, then that member's own token account
+            require_keys_eq!(*pair[0].owner, crate::ID, CustomError::AccountOrderMismatch);
+            let mut member: Account<MemberAccount> = Account::try_from(&pair[0])?;
+            require_keys_eq!(member.group, group_key, CustomError::WrongMemberList);
+            if !member.has_funds() {
+                continue;
+            }
+            let token_account: Account<TokenAccount> = Account::try_from(&pair[1])?;
+            require_keys_eq!(token_account.owner, member.member, CustomError::AccountOrderMismatch);
+            require_keys_eq!(token_account.mint, ctx.accounts.mint.key(), CustomError::WrongMint);
+
+            let amount = clear_contribution(&mut ctx.accounts.group, &mut member, &mut ctx.accounts.escrow)?;
+            member.exit(&crate::ID)?;
+            transfer_from_escrow(
+                &ctx.accounts.token_program,
+                &ctx.accounts.escrow,
+                &ctx.accounts.escrow_token_account,
+                &ctx.accounts.mint,
+                pair[1].clone(),
+                amount,
+            )?;
+        }
+        Ok(())
+    }
+
+    // Once the grace period is over, lets anyone return a member's contribution to them
+    // when the group is still underfunded and was never paid out
+    pub fn claim_expired_refund(ctx: Context<ClaimExpiredRefund>) -> Result<()> {
+        let (now, _) = now(ctx.remaining_accounts)?;
+        require!(!ctx.accounts.group.is_native, CustomError::WrongAssetType);
+        check_expired_refund(&ctx.accounts.group, now)?;
+
+        let amount = clear_contribution(&mut ctx.accounts.group, &mut ctx.accounts.member, &mut ctx.accounts.escrow)?;
+        transfer_from_escrow(
+            &ctx.accounts.token_program,
+            &ctx.accounts.escrow,
+            &ctx.accounts.escrow_token_account,
+            &ctx.accounts.mint,
+            ctx.accounts.member_token_account.to_account_info(),
+            amount,
+        )
+    }
+
+    // Native SOL counterpart of claim_expired_refund
+    pub fn claim_expired_refund_sol(ctx: Context<ClaimExpiredRefundSol>) -> Result<()> {
+        let (now, _) = now(ctx.remaining_accounts)?;
+        require!(ctx.accounts.group.is_native, CustomError::WrongAssetType);
+        check_expired_refund(&ctx.accounts.group, now)?;
+
+        let amount = clear_contribution(&mut ctx.accounts.group, &mut ctx.accounts.member, &mut ctx.accounts.escrow)?;
+        // The escrow is program-owned, so lamports are moved directly
+        ctx.accounts.escrow.sub_lamports(amount)?;
+        ctx.accounts.member_authority.add_lamports(amount)?;
+        Ok(())
+    }
+
+    // Lets a member leave a collecting group before it is due: any contribution is refunded,
+    // the member is uncounted and their account is closed, returning its rent
+    pub fn leave_group(ctx: Context<LeaveGroup>) -> Result<()> {
+        let (now, _) = now(ctx.remaining_accounts)?;
+        let group = &ctx.accounts.group;
+        require!(!group.is_native, CustomError::WrongAssetType);
+        require!(group.is_active && !group.is_cancelled, CustomError::InactiveGroup);
+        require!(now < group.subscription_due, CustomError::TooLate);
+
+        if ctx.accounts.member.has_funds() {
+            let amount = record_refund(&mut ctx.accounts.group, &mut ctx.accounts.member, &mut ctx.accounts.escrow, true, now)?;
+            transfer_from_escrow(
+                &ctx.accounts.token_program,
+                &ctx.accounts.escrow,
+                &ctx.accounts.escrow_token_account,
+                &ctx.accounts.mint,
+                ctx.accounts.member_token_account.to_account_info(),
+                amount,
+            )?;
+        }
+
+        // A late joiner's share may be pinned without a deposit
+        let member = &mut ctx.accounts.member;
+        ctx.accounts.group.unpin(member)?;
+        ctx.accounts.group.remove_member(member.member, member.weight)
+    }
+
+    // Lets the owner remove a member before the due time, refunding whatever they paid and
+    // returning the membership's rent to them
+    pub fn kick_member(ctx: Context<KickMember>) -> Result<()> {
+        let (now, _) = now(ctx.remaining_accounts)?;
+        let group = &ctx.accounts.group;
+        require!(!group.is_native, CustomError::WrongAssetType);
+        require!(group.is_active && !group.is_cancelled, CustomError::InactiveGroup);
+        require!(now < group.subscription_due, CustomError::TooLate);
+
+        if ctx.accounts.member.has_funds() {
+            // Kicked members didn't choose to leave, so they get everything back
+            let amount = record_refund(&mut ctx.accounts.group, &mut ctx.accounts.member, &mut ctx.accounts.escrow, false, now)?;
+            transfer_from_escrow(
+                &ctx.accounts.token_program,
+                &ctx.accounts.escrow,
+                &ctx.accounts.escrow_token_account,
+                &ctx.accounts.mint,
+                ctx.accounts.member_token_account.to_account_info(),
+                amount,
+            )?;
+        }
+
+        // A late joiner's share may be pinned without a deposit
+        let member = &mut ctx.accounts.member;
+        ctx.accounts.group.unpin(member)?;
+        ctx.accounts.group.remove_member(member.member, member.weight)
+    }
+
+    // Records one approver's sign-off on paying out an escrow. Escrows holding more than
+    // the group's approval_above need `threshold` approvals before they can be paid out;
+    // approvals are cleared with each payout. Approving twice has no further effect.
+    pub fn approve_payout(ctx: Context<ApprovePayout>) -> Result<()> {
+        let group = &ctx.accounts.group;
+        require!(group.is_active, CustomError::InactiveGroup);
+        let index = group
+            .approvers
+            .iter()
+            .position(|approver| *approver == ctx.accounts.approver.key())
+            .ok_or(CustomError::NotAnApprover)?;
+        ctx.accounts.escrow.approvals |= 1 << index;
+        Ok(())
+    }
+
+    // Executes payout of one of the group's escrows to the group owner after subscription
+    // due time; groups accepting several mints pay each escrow out with its own call.
+    // Groups with co-recipients pass each recipient's token account, in order, as
+    // remaining accounts. Returns the amount this call paid to the owner or co-recipients,
+    // net of the fee.
+    pub fn execute_payout<'info>(ctx: Context<'_, '_, 'info, 'info, ExecutePayout<'info>>) -> Result<u64> {
+        let (now, remaining_accounts) = now(ctx.remaining_accounts)?;
+        require!(!ctx.accounts.group.is_native, CustomError::WrongAssetType);
+        check_payout(&ctx.accounts.group, now)?;
+        check_settler(&ctx.accounts.group, &ctx.accounts.settler)?;
+        ctx.accounts.group.check_approvals(&ctx.accounts.escrow)?;
+        ctx.accounts.escrow.verify_address(&ctx.accounts.escrow.key())?;
+        require!(ctx.accounts.escrow.paid_cursor == 0, CustomError::PayoutInProgress);
+        // Streamed deposits have already left the vault
+        if !ctx.accounts.group.stream_payouts {
+            ctx.accounts.escrow.check_funded(ctx.accounts.escrow_token_account.amount)?;
+        }
+
+        // Split the escrow between the platform fee and the owner or co-recipients
+        let split = ctx.accounts.group.split_payout(ctx.accounts.escrow.total_held)?;
+        let (fee, amount) = (split.fee, split.amount);
+
+        // Settle the bookkeeping before any funds move so a retry sees a paid-out group
+        settle_payout(&mut ctx.accounts.group, &mut ctx.accounts.escrow, now)?;
+        // Streaming groups already forwarded every deposit; the payout only closes the cycle
+        let streamed = ctx.accounts.group.stream_payouts;
+        if !streamed {
+            pay_out_tokens(
+                &ctx.accounts.token_program,
+                &ctx.accounts.escrow,
+                &ctx.accounts.escrow_token_account,
+                &ctx.accounts.mint,
+                &ctx.accounts.owner_token_account,
+                &ctx.accounts.fee_recipient_token_account,
+                remaining_accounts,
+                &split,
+            )?;
+        }
+
+        emit!(PayoutExecuted {
+            group: ctx.accounts.group.key(),
+            recipient: ctx.accounts.owner_token_account.key(),
+            amount,
+            fee,
+            timestamp: now,
+        });
+        Ok(if streamed { 0 } else { amount })
+    }
+
+    // Pays one escrow of a group with co-recipients over several calls, for lists too long
+    // to pay in one transaction. Each call pays `count` recipients from `start`, which must
+    // pick up where the previous page stopped; their token accounts are passed, in order,
+    // as remaining accounts. The fee goes out with the first page, and the escrow is
+    // settled once the last recipient is paid.
+    pub fn execute_payout_page<'info>(
+        ctx: Context<'_, '_, 'info, 'info, ExecutePayoutPage<'info>>,
+        start: u8,
+        count: u8,
+    ) -> Result<()> {
+        let (now, remaining_accounts) = now(ctx.remaining_accounts)?;
+        require!(!ctx.accounts.group.is_native, CustomError::WrongAssetType);
+        require!(!ctx.accounts.group.recipients.is_empty(), CustomError::NoRecipients);
+        check_payout(&ctx.accounts.group, now)?;
+        check_settler(&ctx.accounts.group, &ctx.accounts.settler)?;
+        ctx.accounts.group.check_approvals(&ctx.accounts.escrow)?;
+        ctx.accounts.escrow.verify_address(&ctx.accounts.escrow.key())?;
+        require!(ctx.accounts.escrow.paid_cycles == ctx.accounts.group.cycle_count, CustomError::AlreadyPaidOut);
+
+        // total_held stays put until the last page, so every page sees the same split
+        let split = ctx.accounts.group.split_payout(ctx.accounts.escrow.total_held)?;
+        require!(start == ctx.accounts.escrow.paid_cursor, CustomError::PageOutOfOrder);
+        let end = usize::from(start) + usize::from(count);
+        require!(count > 0 && end <= split.shares.len(), CustomError::PageOutOfOrder);
+        if start == 0 {
+            // One escrow is paged at a time, so the group knows when the last page is done
+            require!(!ctx.accounts.group.payout_in_progress, CustomError::PayoutInProgress);
+            ctx.accounts.escrow.check_funded(ctx.accounts.escrow_token_account.amount)?;
+        }
+
+        if start == 0 && split.fee > 0 {
+            transfer_from_escrow(
+                &ctx.accounts.token_program,
+                &ctx.accounts.escrow,
+                &ctx.accounts.escrow_token_account,
+                &ctx.accounts.mint,
+                ctx.accounts.fee_recipient_token_account.to_account_info(),
+                split.fee,
+            )?;
+        }
+        pay_recipients(
+            &ctx.accounts.token_program,
+            &ctx.accounts.escrow,
+            &ctx.accounts.escrow_token_account,
+            &ctx.accounts.mint,
+            &split.shares[usize::from(start)..end],
+            remaining_accounts,
+        )?;
+
+        if end < split.shares.len() {
+            ctx.accounts.escrow.paid_cursor = end as u8;
+            ctx.accounts.group.payout_in_progress = true;
+            return Ok(());
+        }
+        ctx.accounts.escrow.paid_cursor = 0;
+        ctx.accounts.group.payout_in_progress = false;
+        settle_payout(&mut ctx.accounts.group, &mut ctx.accounts.escrow, now)?;
+
+        emit!(PayoutExecuted {
+            group: ctx.accounts.group.key(),
+            recipient: Pubkey::default(), // Split between the co-recipients
+            amount: split.amount,
+            fee: split.fee,
+            timestamp: now,
+        });
+        Ok(())
+    }
+
+    // Executes payout of a native SOL group's escrow to the group owner. Groups with
+    // co-recipients pass each recipient's wallet, in order, as remaining accounts.
+    pub fn execute_payout_sol(ctx: Context<ExecutePayoutSol>) -> Result<()> {
+        let (now, remaining_accounts) = now(ctx.remaining_accounts)?;
+        require!(ctx.accounts.group.is_native, CustomError::WrongAssetType);
+        check_payout(&ctx.accounts.group, now)?;
+        check_settler(&ctx.accounts.group, &ctx.accounts.settler)?;
+        ctx.accounts.group.check_approvals(&ctx.accounts.escrow)?;
+
+        // Split the escrow between the platform fee and the owner or co-recipients
+        let split = ctx.accounts.group.split_payout(ctx.accounts.escrow.total_held)?;
+        let (fee, amount) = (split.fee, split.amount);
+
+        // Settle the bookkeeping before any funds move so a retry sees a paid-out group
+        settle_payout(&mut ctx.accounts.group, &mut ctx.accounts.escrow, now)?;
+        pay_out_lamports(
+            &ctx.accounts.escrow,
+            &ctx.accounts.payout_recipient,
+            &ctx.accounts.fee_recipient,
+            remaining_accounts,
+            &split,
+        )?;
+
+        emit!(PayoutExecuted {
+            group: ctx.accounts.group.key(),
+            recipient: ctx.accounts.payout_recipient.key(),
+            amount,
+            fee,
+            timestamp: now,
+        });
+        Ok(())
+    }
+
+    // Lets the owner sweep whatever an underfunded token group collected, once members
+    // have had EMERGENCY_DELAY after the grace period to complete it. The sweep is split
+    // the same way as a payout, so co-recipients' accounts are passed the same way too, and
+    // needs the same approvals.
+    pub fn emergency_withdraw<'info>(
+        ctx: Context<'_, '_, 'info, 'info, EmergencyWithdraw<'info>>,
+    ) -> Result<()> {
+        let (now, remaining_accounts) = now(ctx.remaining_accounts)?;
+        require!(!ctx.accounts.group.is_native, CustomError::WrongAssetType);
+        check_emergency_withdraw(&ctx.accounts.group, now)?;
+        ctx.accounts.group.check_approvals(&ctx.accounts.escrow)?;
+        ctx.accounts.escrow.verify_address(&ctx.accounts.escrow.key())?;
+        require!(ctx.accounts.escrow.paid_cursor == 0, CustomError::PayoutInProgress);
+
+        let split = ctx.accounts.group.split_payout(ctx.accounts.escrow.total_held)?;
+        let (fee, amount) = (split.fee, split.amount);
+
+        // Settle the bookkeeping before any funds move so a retry sees a paid-out group
+        settle_payout(&mut ctx.accounts.group, &mut ctx.accounts.escrow, now)?;
+        // Streaming groups already forwarded every deposit; the payout only closes the cycle
+        if !ctx.accounts.group.stream_payouts {
+            pay_out_tokens(
+                &ctx.accounts.token_program,
+                &ctx.accounts.escrow,
+                &ctx.accounts.escrow_token_account,
+                &ctx.accounts.mint,
+                &ctx.accounts.owner_token_account,
+                &ctx.accounts.fee_recipient_token_account,
+                remaining_accounts,
+                &split,
+            )?;
+        }
+
+        emit!(EmergencyWithdrawn {
+            group: ctx.accounts.group.key(),
+            recipient: ctx.accounts.owner_token_account.key(),
+            amount,
+            fee,
+            timestamp: now,
+        });
+        Ok(())
+    }
+
+    // Native SOL counterpart of emergency_withdraw
+    pub fn emergency_withdraw_sol(ctx: Context<EmergencyWithdrawSol>) -> Result<()> {
+        let (now, remaining_accounts) = now(ctx.remaining_accounts)?;
+        require!(ctx.accounts.group.is_native, CustomError::WrongAssetType);
+        check_emergency_withdraw(&ctx.accounts.group, now)?;
+        ctx.accounts.group.check_approvals(&ctx.accounts.escrow)?;
+
+        let split = ctx.accounts.group.split_payout(ctx.accounts.escrow.total_held)?;
+        let (fee, amount) = (split.fee, split.amount);
+
+        // Settle the bookkeeping before any funds move so a retry sees a paid-out group
+        settle_payout(&mut ctx.accounts.group, &mut ctx.accounts.escrow, now)?;
+        pay_out_lamports(
+            &ctx.accounts.escrow,
+            &ctx.accounts.payout_recipient,
+            &ctx.accounts.fee_recipient,
+            remaining_accounts,
+            &split,
+        )?;
+
+        emit!(EmergencyWithdrawn {
+            group: ctx.accounts.group.key(),
+            recipient: ctx.accounts.payout_recipient.key(),
+            amount,
+            fee,
+            timestamp: now,
+        });
+        Ok(())
+    }
+
+    // Returns tokens sent straight to the vault, beyond what deposits account for, to the owner
+    pub fn withdraw_excess(ctx: Context<WithdrawExcess>) -> Result<()> {
+        // A cancelled group is never paid out, so the refund penalties it kept are no
+        // longer tracked and leave with the surplus
+        if ctx.accounts.group.is_cancelled {
+            let penalties = ctx.accounts.escrow.penalties;
+            let escrow = &mut ctx.accounts.escrow;
+            escrow.total_held = escrow.total_held.checked_sub(penalties).ok_or(CustomError::Overflow)?;
+            escrow.penalties = 0;
+            let group = &mut ctx.accounts.group;
+            group.total_held = group.total_held.checked_sub(penalties).ok_or(CustomError::Overflow)?;
+            group.fees_held = group.fees_held.checked_sub(penalties).ok_or(CustomError::Overflow)?;
+        }
+
+        // Tracked deposits stay in the vault, unless they were streamed out on arrival;
+        // only the untracked surplus can leave
+        let escrow = &ctx.accounts.escrow;
+        let tracked = if ctx.accounts.group.stream_payouts {
+            0
+        } else {
+            escrow.total_held.checked_add(escrow.credit_held).ok_or(CustomError::Overflow)?
+        };
+        let excess = ctx
+            .accounts
+            .escrow_token_account
+            .amount
+            .checked_sub(tracked)
+            .ok_or(CustomError::Overflow)?;
+        require!(excess > 0, CustomError::NoExcess);
+
+        transfer_from_escrow(
+            &ctx.accounts.token_program,
+            &ctx.accounts.escrow,
+            &ctx.accounts.escrow_token_account,
+            &ctx.accounts.mint,
+            ctx.accounts.owner_token_account.to_account_info(),
+            excess,
+        )
+    }
+
+    // Lends `amount` of an escrow's vault to the group's yield program, which must still be
+    // on the config's allowlist. `data` is the yield program's deposit instruction; the
+    // accounts it takes are passed, in order, as remaining accounts, and the escrow signs
+    // wherever it appears among them. At most the escrow's own deposits can be lent, and
+    // the vault must go down by exactly `amount`.
+    pub fn deposit_to_yield<'info>(
+        ctx: Context<'_, '_, 'info, 'info, YieldTransfer<'info>>,
+        amount: u64,
+        data: Vec<u8>,
+    ) -> Result<()> {
+        let group = &ctx.accounts.group;
+        require!(group.yield_enabled, CustomError::YieldDisabled);
+        require!(group.is_active && !group.is_cancelled, CustomError::InactiveGroup);
+        require!(amount > 0, CustomError::WrongAmount);
+        require!(
+            load_config(&ctx.accounts.config)?.yield_programs.contains(&group.yield_program),
+            CustomError::YieldProgramNotAllowed
+        );
+
+        let before = ctx.accounts.escrow_token_account.amount;
+        let lendable = before.min(ctx.accounts.escrow.expected_vault_balance(group));
+        require!(amount <= lendable, CustomError::ExceedsHoldings);
+        invoke_yield_program(&ctx.accounts.escrow, &ctx.accounts.yield_program, ctx.remaining_accounts, data)?;
+        ctx.accounts.escrow_token_account.reload()?;
+        require!(
+            before.checked_sub(ctx.accounts.escrow_token_account.amount) == Some(amount),
+            CustomError::YieldMismatch
+        );
+
         let escrow = &mut ctx.accounts.escrow;
+        escrow.in_yield = escrow.in_yield.checked_add(amount).ok_or(CustomError::Overflow)?;
+        Ok(())
+    }
+
+    // Brings lent tokens back from the group's yield program, called like deposit_to_yield
+    // with the yield program's withdraw instruction. Whatever comes back beyond what was
+    // lent is interest; it stays in the vault as excess for the owner's withdraw_excess.
+    pub fn withdraw_from_yield<'info>(
+        ctx: Context<'_, '_, 'info, 'info, YieldTransfer<'info>>,
+        data: Vec<u8>,
+    ) -> Result<()> {
+        require!(ctx.accounts.group.yield_enabled, CustomError::YieldDisabled);
+        return_from_yield(
+            &mut ctx.accounts.escrow,
+            &mut ctx.accounts.escrow_token_account,
+            &ctx.accounts.yield_program,
+            ctx.remaining_accounts,
+            data,
+        )
+    }
+
+    // Lets any member bring lent tokens back, called like withdraw_from_yield, so refunds
+    // and payouts blocked by YieldOutstanding don't wait on the owner. The vault can only
+    // go up, so a member can't move funds out this way.
+    pub fn recall_from_yield<'info>(
+        ctx: Context<'_, '_, 'info, 'info, RecallFromYield<'info>>,
+        data: Vec<u8>,
+    ) -> Result<()> {
+        require!(ctx.accounts.escrow.in_yield > 0, CustomError::NothingLent);
+        return_from_yield(
+            &mut ctx.accounts.escrow,
+            &mut ctx.accounts.escrow_token_account,
+            &ctx.accounts.yield_program,
+            ctx.remaining_accounts,
+            data,
+        )
+    }
+
+    // Winds down a group that won't be paid out; members can then refund
+    pub fn cancel_group(ctx: Context<CancelGroup>) -> Result<()> {
+        let group = &mut ctx.accounts.group;
+        require!(group.is_active, CustomError::InactiveGroup);
+        // A paged payout is finished first; its recipients are already partly paid
+        require!(!group.payout_in_progress, CustomError::PayoutInProgress);
+
+        group.cancel();
+        Ok(())
+    }
+
+    // Lets a member tell the owner whether they plan to auto-pay the next cycle. Purely a
+    // signal: the program never pulls funds, and the flag carries over between cycles.
+    pub fn set_auto_pay(ctx: Context<SetAutoPay>, enabled: bool) -> Result<()> {
+        ctx.accounts.member.auto_pay = enabled;
+        Ok(())
+    }
+
+    // Lets any member contest an upcoming payout, freezing it until the owner resolves it
+    pub fn raise_dispute(ctx: Context<RaiseDispute>) -> Result<()> {
+        let group = &mut ctx.accounts.group;
+        require!(!group.is_cancelled, CustomError::GroupCancelled);
+        require!(group.is_active, CustomError::InactiveGroup);
+        require!(!group.payout_in_progress, CustomError::PayoutInProgress);
+
+        group.disputed = true;
+        Ok(())
+    }
+
+    // Settles a dispute: `release` lets the payout go ahead, otherwise the group is
+    // cancelled so every member can refund
+    pub fn resolve_dispute(ctx: Context<ResolveDispute>, release: bool) -> Result<()> {
+        let group = &mut ctx.accounts.group;
+        require!(group.disputed, CustomError::NotDisputed);
+        require!(!group.payout_in_progress, CustomError::PayoutInProgress);
+
+        group.disputed = false;
+        if !release {
+            group.cancel();
+        }
+        Ok(())
+    }
+
+    // Pins the token account one escrow pays out to, e.g. when the payout recipient's usual
+    // account has been frozen or closed. It must be a live account of the escrow's mint
+    // owned by the payout recipient.
+    pub fn set_payout_account(ctx: Context<SetPayoutAccount>, new_account: Pubkey) -> Result<()> {
+        let account = &ctx.accounts.new_payout_account;
+        require_keys_eq!(account.mint, ctx.accounts.escrow.mint, CustomError::WrongMint);
+        require_keys_eq!(account.owner, ctx.accounts.group.payout_recipient, CustomError::WrongPayoutRecipient);
+        require!(!account.is_frozen(), CustomError::PayoutAccountFrozen);
+
+        ctx.accounts.escrow.payout_account = new_account;
+        Ok(())
+    }
+
+    // Temporarily halts (or resumes) deposits without cancelling the group
+    pub fn set_paused(ctx: Context<SetPaused>, paused: bool) -> Result<()> {
+        ctx.accounts.group.is_paused = paused;
+        Ok(())
+    }
+
+    // Nominates a new owner; the transfer completes once they accept
+    pub fn transfer_ownership(ctx: Context<TransferOwnership>, new_owner: Pubkey) -> Result<()> {
+        ctx.accounts.group.pending_owner = Some(new_owner);
+        Ok(())
+    }
+
+    // Completes an ownership transfer started by the current owner
+    pub fn accept_ownership(ctx: Context<AcceptOwnership>) -> Result<()> {
+        let group = &mut ctx.accounts.group;
+        group.owner = ctx.accounts.new_owner.key();
+        group.pending_owner = None;
+        Ok(())
+    }
+
+    // Closes a finished group, its last escrow and the escrow's token account, returning
+    // their rent to the owner
+    pub fn close_group(ctx: Context<CloseGroup>) -> Result<()> {
+        require!(!ctx.accounts.group.is_active, CustomError::GroupStillActive);
+        require!(ctx.accounts.escrow.is_empty(), CustomError::EscrowNotEmpty);
+        require!(ctx.accounts.group.escrow_count == 1, CustomError::EscrowsOpen);
+        // The vault goes first, while the escrow can still sign for it; the escrow and
+        // group are closed by their `close` constraints once the instruction returns
+        close_vault(
+            &ctx.accounts.token_program,
+            &ctx.accounts.group,
+            &ctx.accounts.escrow,
+            &ctx.accounts.vault,
+            ctx.accounts.owner.to_account_info(),
+        )
+    }
+
+    // Lets anyone close a group that reached its due time without a single member,
+    // returning the rent of the group and its escrow to the owner
+    pub fn sweep_empty_group(ctx: Context<SweepEmptyGroup>) -> Result<()> {
+        let (now, _) = now(ctx.remaining_accounts)?;
+        let group = &ctx.accounts.group;
+        require!(now >= group.subscription_due, CustomError::TooEarly);
+        require!(group.member_count == 0, CustomError::GroupNotEmpty);
+        require!(ctx.accounts.escrow.is_empty(), CustomError::EscrowNotEmpty);
+        require!(group.escrow_count == 1, CustomError::EscrowsOpen);
+        close_vault(
+            &ctx.accounts.token_program,
+            group,
+            &ctx.accounts.escrow,
+            &ctx.accounts.vault,
+            ctx.accounts.owner.to_account_info(),
+        )
+    }
+
+    // Closes one of several escrows of a finished group, returning its rent to the owner
+    pub fn close_escrow(ctx: Context<CloseEscrow>) -> Result<()> {
+        let group = &mut ctx.accounts.group;
+        require!(!group.is_active, CustomError::GroupStillActive);
+        require!(ctx.accounts.escrow.is_empty(), CustomError::EscrowNotEmpty);
+        // The last escrow goes with the group in close_group
+        require!(group.escrow_count > 1, CustomError::LastEscrow);
+        group.escrow_count -= 1;
+        close_vault(
+            &ctx.accounts.token_program,
+            &ctx.accounts.group,
+            &ctx.accounts.escrow,
+            &ctx.accounts.vault,
+            ctx.accounts.owner.to_account_info(),
+        )
+    }
+
+    // Renames a group, resizing its account to fit the new name. The owner pays the rent of
+    // a longer name and gets back the rent of a shorter one. The group keeps its address,
+    // which stays derived from the name it was created with.
+    pub fn update_name(ctx: Context<UpdateName>, new_name: String) -> Result<()> {
+        ctx.accounts.group.group_name = validate_name(&new_name)?.to_string();
+        Ok(())
+    }
+
+    // Pushes the due time of an active group back, by at most MAX_DUE_EXTENSION
+    pub fn extend_due(ctx: Context<ExtendDue>, new_due: i64) -> Result<()> {
+        let (now, _) = now(ctx.remaining_accounts)?;
+        let group = &mut ctx.accounts.group;
+        require!(!group.is_cancelled, CustomError::GroupCancelled);
+        require!(group.is_active, CustomError::InactiveGroup);
+        // Moving the due time back would hold a paged payout's last pages TooEarly
+        require!(!group.payout_in_progress, CustomError::PayoutInProgress);
+        require!(new_due > group.subscription_due, CustomError::DueNotLater);
+        require!(
+            new_due - group.subscription_due <= MAX_DUE_EXTENSION,
+            CustomError::DueTooFar
+        );
+        validate_due(new_due, group.grace_period, now)?;
+
+        // The deposit deadline keeps its lead on the due time
+        if group.deposit_deadline != 0 {
+            group.deposit_deadline += new_due - group.subscription_due;
+        }
+        group.subscription_due = new_due;
+        Ok(())
+    }
+
+    // Changes the price of an active group before anyone has deposited toward it. Shares are
+    // derived from total_cost when needed, so nothing else has to be recomputed, as long as
+    // no member still has a share pinned against the old price.
+    pub fn update_cost(ctx: Context<UpdateCost>, new_cost: u64) -> Result<()> {
+        let group = &mut ctx.accounts.group;
+        require!(!group.is_cancelled, CustomError::GroupCancelled);
+        require!(group.is_active, CustomError::InactiveGroup);
+        require!(group.escrow_count == 1, CustomError::EscrowsOpen);
+        require!(ctx.accounts.escrow.total_held == 0, CustomError::DepositsMade);
+        require!(group.pinned_count == 0, CustomError::SharesPinned);
+        require!(new_cost > 0, CustomError::ZeroCost);
+        require!(new_cost <= load_config(&ctx.accounts.config)?.cost_cap(), CustomError::CostTooHigh);
+
+        // The new price replaces any surcharge added on top of the old one
+        group.total_cost = new_cost;
+        group.surcharge = 0;
+        group.check_shares()
+    }
+
+    // Adds a one-time charge, e.g. a setup fee, to the current cycle of an active group;
+    // renew_group takes it back off the cost. Every member account of the group must be
+    // passed, writable, in `remaining_accounts`. `extra` is split by weight the way shares
+    // are, with the dust going to members in the order passed. Members whose share is
+    // already pinned have their part added on top of it, and those who had paid in full owe
+    // the difference again before the payout; everyone else picks the surcharge up through
+    // the raised cost when their share is pinned.
+    pub fn add_surcharge<'info>(
+        ctx: Context<'_, '_, 'info, 'info, AddSurcharge<'info>>,
+        extra: u64,
+    ) -> Result<()> {
+        let (now, member_accounts) = now(ctx.remaining_accounts)?;
+        let group = &mut ctx.accounts.group;
+        require!(!group.is_cancelled, CustomError::GroupCancelled);
+        require!(group.is_active, CustomError::InactiveGroup);
+        require!(!group.payout_in_progress, CustomError::PayoutInProgress);
+        require!(extra > 0, CustomError::WrongAmount);
+        let total_cost = group.total_cost.checked_add(extra).ok_or(CustomError::Overflow)?;
+        require!(total_cost <= load_config(&ctx.accounts.config)?.cost_cap(), CustomError::CostTooHigh);
+
+        // Each member must appear exactly once so nobody's share misses the surcharge
+        require!(
+            member_accounts.len() == usize::from(group.member_count),
+            CustomError::WrongMemberList
+        );
+        let mut seen: Vec<Pubkey> = Vec::with_capacity(member_accounts.len());
+        for (i, info) in member_accounts.iter().enumerate() {
+            require!(!seen.contains(info.key), CustomError::WrongMemberList);
+            seen.push(info.key());
+
+            let mut member: Account<MemberAccount> = Account::try_from(info)?;
+            require_keys_eq!(member.group, group.key(), CustomError::WrongMemberList);
+
+            if member.owed == 0 {
+                continue;
+            }
+            let added = required_deposit(extra, group.member_count, member.weight, group.total_weight, Some(i));
+            member.owed = member.owed.checked_add(added).ok_or(CustomError::Overflow)?;
+            group.pinned_cost = group.pinned_cost.checked_add(added).ok_or(CustomError::Overflow)?;
+            if member.has_paid && member.contributed < member.owed {
+                member.has_paid = false;
+                member.cycles_paid = member.cycles_paid.checked_sub(1).ok_or(CustomError::Overflow)?;
+                group.paid_count = group.paid_count.checked_sub(1).ok_or(CustomError::Overflow)?;
+            }
+            member.exit(&crate::ID)?;
+        }
+        group.total_cost = total_cost;
+        group.surcharge = group.surcharge.checked_add(extra).ok_or(CustomError::Overflow)?;
+
+        emit!(SurchargeAdded {
+            group: group.key(),
+            extra,
+            total_cost,
+            timestamp: now,
+        });
+        Ok(())
+    }
+
+    // Starts a new billing cycle after a payout. Every member account of the group must be
+    // passed, writable, in `remaining_accounts` so their payments can be cleared.
+    pub fn renew_group<'info>(
+        ctx: Context<'_, '_, 'info, 'info, RenewGroup<'info>>,
+        next_due: i64,
+    ) -> Result<()> {
+        let (now, member_accounts) = now(ctx.remaining_accounts)?;
+        let group = &mut ctx.accounts.group;
+        require!(!group.is_cancelled, CustomError::GroupCancelled);
+        require!(!group.is_active, CustomError::NotPaidOut);
+        validate_due(next_due, group.grace_period, now)?;
+        require!(next_due > now, CustomError::DueInPast);
+        // The new cycle's deposit deadline keeps the same lead on its due time
+        let deposit_deadline = match group.deposit_deadline {
+            0 => 0,
+            deadline => next_due - (group.subscription_due - deadline),
+        };
+        require!(deposit_deadline == 0 || deposit_deadline > now, CustomError::InvalidDepositDeadline);
+
+        // Each member must appear exactly once so nobody carries a payment into the new cycle
+        require!(
+            member_accounts.len() == usize::from(group.member_count),
+            CustomError::WrongMemberList
+        );
+        let mut seen: Vec<Pubkey> = Vec::with_capacity(member_accounts.len());
+        let mut summary = Vec::with_capacity(member_accounts.len());
+        for info in member_accounts.iter() {
+            require!(!seen.contains(info.key), CustomError::WrongMemberList);
+            seen.push(info.key());
+
+            let mut member: Account<MemberAccount> = Account::try_from(info)?;
+            require_keys_eq!(member.group, group.key(), CustomError::WrongMemberList);
+
+            // Record how the member did in the cycle that just ended before clearing it
+            summary.push(MemberReliability {
+                member: member.member,
+                paid: member.has_paid,
+                paid_late: member.paid_late,
+                cycles_paid: member.cycles_paid,
+                auto_pay: member.auto_pay,
+            });
+            member.contributed = 0;
+            member.owed = 0; // Re-derived for the new cycle's members
+            member.has_paid = false;
+            member.paid_late = false;
+            member.late_fee_paid = 0;
+            member.first_deposit_at = 0;
+            member.exit(&crate::ID)?;
+        }
+
+        emit!(RenewalSummary {
+            group: group.key(),
+            cycle: group.cycle_count,
+            members: summary,
+            next_due,
+        });
+
+        group.deposit_deadline = deposit_deadline;
+        // Every member was passed and unpinned above
+        group.pinned_cost = 0;
+        group.pinned_weight = 0;
+        group.pinned_count = 0;
+        // One-time charges apply to the cycle they were added in
+        group.total_cost = group.total_cost.checked_sub(group.surcharge).ok_or(CustomError::Overflow)?;
+        group.surcharge = 0;
+        group.check_shares()?;
+        group.subscription_due = next_due;
+        group.paid_count = 0;
+        group.is_active = true;
+        Ok(())
+    }
+
+    // Returns how much a member still owes toward their share, without modifying state
+    pub fn get_outstanding(ctx: Context<GetOutstanding>) -> Result<u64> {
+        ctx.accounts.group.outstanding_of(&ctx.accounts.member)
+    }
+
+    // Compares what a token escrow's vault should hold with what it does hold, without
+    // modifying state. Returns whether the two match; the SolvencyChecked event carries
+    // the delta, positive for tokens sent straight to the vault, negative for a shortfall.
+    pub fn check_solvency(ctx: Context<CheckSolvency>) -> Result<bool> {
+        require!(!ctx.accounts.group.is_native, CustomError::WrongAssetType);
+        let expected = ctx.accounts.escrow.expected_vault_balance(&ctx.accounts.group);
+        let actual = ctx.accounts.escrow_token_account.amount;
+        let delta = i128::from(actual) - i128::from(expected);
+        let delta = i64::try_from(delta).unwrap_or(if delta > 0 { i64::MAX } else { i64::MIN });
+
+        emit!(SolvencyChecked {
+            group: ctx.accounts.group.key(),
+            escrow: ctx.accounts.escrow.key(),
+            expected,
+            actual,
+            delta,
+        });
+        Ok(delta == 0)
+    }
+
+    // Returns the seconds left until execute_payout's time checks pass, counting the grace
+    // period and the settlement delay, or 0 once they do. Funding and the other payout
+    // conditions aren't considered. Test builds read the same clock as execute_payout.
+    pub fn seconds_until_payout(ctx: Context<SecondsUntilPayout>) -> Result<i64> {
+        let (now, _) = now(ctx.remaining_accounts)?;
+        Ok(ctx.accounts.group.payout_opens_at().saturating_sub(now).max(0))
+    }
+
+    // Returns a snapshot of a group's collection progress, without modifying state
+    pub fn get_group_status(ctx: Context<GetGroupStatus>) -> Result<GroupStatus> {
+        let (now, _) = now(ctx.remaining_accounts)?;
+        let group = &ctx.accounts.group;
+        Ok(GroupStatus {
+            member_count: group.member_count,
+            paid_count: group.paid_count,
+            total_held: ctx.accounts.escrow.total_held,
+            is_active: group.is_active,
+            seconds_until_due: group.subscription_due.saturating_sub(now).max(0),
+        })
+    }
+}
+
+// Deposit owed by the member of `weight` at `index` in invite order when `total_cost` is
+// split between `member_count` members weighing `total_weight` in all: the member's
+// weighted share plus an even part of the rounding dust, and one more unit for each of the
+// first `dust % member_count` members. Over every member the deposits add up to exactly
+// `total_cost`. A member without an index gets no positional unit. Zero for an empty group.
+pub fn required_deposit(total_cost: u64, member_count: u8, weight: u16, total_weight: u16, index: Option<usize>) -> u64 {
+    if member_count == 0 || total_weight == 0 {
+        return 0;
+    }
+    let total_weight = u64::from(total_weight);
+    let dust = total_cost % total_weight;
+    let first = index.is_some_and(|index| (index as u64) < dust % u64::from(member_count));
+    (total_cost / total_weight)
+        .saturating_mul(u64::from(weight))
+        .saturating_add(dust / u64::from(member_count))
+        .saturating_add(u64::from(first))
+}
+
+// Derives the membership PDA of `member` in `group` along with its canonical bump. Each
+// wallet has one MemberAccount per group, at the address seeded by the group key followed
+// by the wallet key; invite_member creates it there and batch_invite checks against it.
+pub fn member_pda(group: &Pubkey, member: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[group.as_ref(), member.as_ref()], &crate::ID)
+}
+
+// Offsets of MemberAccount fields in its serialized data, after the 8-byte discriminator.
+// Everything up to has_paid is fixed-size, so these hold for every member account.
+pub const MEMBER_GROUP_OFFSET: usize = 8;
+pub const MEMBER_HAS_PAID_OFFSET: usize = 8 + 32 + 32 + 8;
+
+// A getProgramAccounts memcmp filter: `bytes` must appear at `offset` in the account data
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MemcmpFilter {
+    pub offset: usize,
+    pub bytes: Vec<u8>,
+}
+
+impl MemcmpFilter {
+    // Whether serialized account `data` passes the filter
+    pub fn matches(&self, data: &[u8]) -> bool {
+        data.get(self.offset..self.offset + self.bytes.len()) == Some(&self.bytes[..])
+    }
+}
+
+// Filters selecting the member accounts of `group` that haven't paid this cycle, so a
+// reminder service can list them with one getProgramAccounts call instead of fetching
+// every member
+pub fn unpaid_filter(group: &Pubkey) -> Vec<MemcmpFilter> {
+    vec![
+        MemcmpFilter { offset: 0, bytes: MemberAccount::DISCRIMINATOR.to_vec() },
+        MemcmpFilter { offset: MEMBER_GROUP_OFFSET, bytes: group.to_bytes().to_vec() },
+        MemcmpFilter { offset: MEMBER_HAS_PAID_OFFSET, bytes: vec![u8::from(false)] },
+    ]
+}
+
+// Trims a group name and checks it is non-empty, at most MAX_NAME_LEN bytes, and free of
+// control characters that could garble how front-ends show it
+fn validate_name(name: &str) -> Result<&str> {
+    let name = name.trim();
+    require!(name.len() <= MAX_NAME_LEN, CustomError::NameTooLong);
+    require!(!name.is_empty() && !name.chars().any(char::is_control), CustomError::InvalidName);
+    Ok(name)
+}
+
+// Splits a group name into the two seeds its PDA is derived with, since a seed holds at
+// most 32 bytes. Seeds are hashed back to back, so the address is the same as if the name
+// were a single seed. The name is trimmed as create_group stores it; names past
+// MAX_NAME_LEN are cut short here and rejected by create_group.
+pub fn group_name_seeds(name: &str) -> [&[u8]; 2] {
+    let bytes = name.trim().as_bytes();
+    let head = bytes.len().min(MAX_SEED_LEN);
+    let tail = bytes.len().min(MAX_NAME_LEN);
+    [&bytes[..head], &bytes[head..tail]]
+}
+
+// Creates a program account of `space` bytes at the PDA signed for by `seeds`, with `payer`
+// funding it up to `lamports`. Like Anchor's `init`, an address that was already sent
+// lamports is topped up, allocated and assigned instead, as create_account would fail on it.
+fn create_pda_account<'info>(
+    system_program: &Program<'info, System>,
+    payer: &AccountInfo<'info>,
+    account: &AccountInfo<'info>,
+    lamports: u64,
+    space: usize,
+    seeds: &[&[u8]],
+) -> Result<()> {
+    let signer = &[seeds];
+    let current = account.lamports();
+    if current == 0 {
+        let cpi_accounts = system_program::CreateAccount { from: payer.clone(), to: account.clone() };
+        let cpi_ctx = CpiContext::new_with_signer(system_program.to_account_info(), cpi_accounts, signer);
+        return system_program::create_account(cpi_ctx, lamports, space as u64, &crate::ID);
+    }
+
+    let top_up = lamports.saturating_sub(current);
+    if top_up > 0 {
+        let cpi_accounts = system_program::Transfer { from: payer.clone(), to: account.clone() };
+        let cpi_ctx = CpiContext::new(system_program.to_account_info(), cpi_accounts);
+        system_program::transfer(cpi_ctx, top_up)?;
+    }
+    let cpi_accounts = system_program::Allocate { account_to_allocate: account.clone() };
+    let cpi_ctx = CpiContext::new_with_signer(system_program.to_account_info(), cpi_accounts, signer);
+    system_program::allocate(cpi_ctx, space as u64)?;
+    let cpi_accounts = system_program::Assign { account_to_assign: account.clone() };
+    let cpi_ctx = CpiContext::new_with_signer(system_program.to_account_info(), cpi_accounts, signer);
+    system_program::assign(cpi_ctx, &crate::ID)
+}
+
+// Transfers tokens out of the escrow's token account, signed by the escrow PDA
+fn transfer_from_escrow<'info>(
+    token_program: &Program<'info, Token>,
+    escrow: &Account<'info, EscrowAccount>,
+    escrow_token_account: &Account<'info, TokenAccount>,
+    mint: &Account<'info, Mint>,
+    to: AccountInfo<'info>,
+    amount: u64,
+) -> Result<()> {
+    // Some token programs reject zero-amount transfers, e.g. when paying out an empty escrow
+    if amount == 0 {
+        return Ok(());
+    }
+    let seeds = escrow.signer_seeds();
+    let signer = &[&seeds[..]];
+    let cpi_accounts = TransferChecked {
+        from: escrow_token_account.to_account_info(),
+        mint: mint.to_account_info(),
+        to,
+        authority: escrow.to_account_info(),
+    };
+    let cpi_ctx = CpiContext::new_with_signer(token_program.to_account_info(), cpi_accounts, signer);
+    token::transfer_checked(cpi_ctx, amount, mint.decimals)
+}
+
+// Closes a token escrow's token account as part of closing the escrow, sending its rent
+// to the owner; native escrows have no token account
+fn close_vault<'info>(
+    token_program: &Program<'info, Token>,
+    group: &GroupAccount,
+    escrow: &Account<'info, EscrowAccount>,
+    vault: &Option<Account<'info, TokenAccount>>,
+    owner: AccountInfo<'info>,
+) -> Result<()> {
+    if group.is_native {
+        return Ok(());
+    }
+    let vault = vault.as_ref().ok_or(CustomError::WrongVault)?;
+    // Untracked leftovers have to be taken out with withdraw_excess first
+    require!(vault.amount == 0, CustomError::EscrowNotEmpty);
+
+    let seeds = escrow.signer_seeds();
+    let signer = &[&seeds[..]];
+    let cpi_accounts = CloseAccount {
+        account: vault.to_account_info(),
+        destination: owner,
+        authority: escrow.to_account_info(),
+    };
+    let cpi_ctx = CpiContext::new_with_signer(token_program.to_account_info(), cpi_accounts, signer);
+    token::close_account(cpi_ctx)
+}
+
+// Reads the program config, falling back to the built-in defaults while it doesn't exist
+fn load_config(info: &AccountInfo) -> Result<ProgramConfig> {
+    if info.data_is_empty() {
+        return Ok(ProgramConfig::default());
+    }
+    require_keys_eq!(*info.owner, crate::ID, ErrorCode::AccountOwnedByWrongProgram);
+    ProgramConfig::try_deserialize(&mut &info.try_borrow_data()?[..])
+}
+
+// Fails while the config admin has paused the program
+fn check_not_paused(config: &AccountInfo) -> Result<()> {
+    require!(!load_config(config)?.paused, CustomError::ProgramPaused);
+    Ok(())
+}
+
+// Checks a due time is positive, at most MAX_DUE_AHEAD away, and leaves room to add the
+// grace period without overflowing
+fn validate_due(due: i64, grace_period: i64, now: i64) -> Result<()> {
+    require!(due > 0 && due - now <= MAX_DUE_AHEAD, CustomError::InvalidDueDate);
+    grace_end_of(due, grace_period)?;
+    Ok(())
+}
+
+// Last moment deposits are accepted for a deadline of `due`
+fn grace_end_of(due: i64, grace_period: i64) -> Result<i64> {
+    due.checked_add(grace_period).ok_or_else(|| error!(CustomError::InvalidDueDate))
+}
+
+// Checks a payout approver list: bounded in length, free of duplicates, and with a
+// threshold that its approvers can reach
+fn validate_approvers(approvers: &[Pubkey], threshold: u8) -> Result<()> {
+    require!(approvers.len() <= MAX_APPROVERS, CustomError::InvalidApprovers);
+    require!(usize::from(threshold) <= approvers.len(), CustomError::InvalidApprovers);
+    require!(approvers.is_empty() || threshold > 0, CustomError::InvalidApprovers);
+    for (i, approver) in approvers.iter().enumerate() {
+        require!(!approvers[..i].contains(approver), CustomError::InvalidApprovers);
+    }
+    Ok(())
+}
+
+// Checks a co-recipient list: bounded in length and, when set, covering exactly 100%
+fn validate_recipients(recipients: &[Recipient]) -> Result<()> {
+    require!(recipients.len() <= MAX_RECIPIENTS, CustomError::TooManyRecipients);
+    if !recipients.is_empty() {
+        let total_bps: u64 = recipients.iter().map(|r| u64::from(r.bps)).sum();
+        require!(total_bps == BPS_DENOMINATOR, CustomError::InvalidRecipientSplit);
+    }
+    Ok(())
+}
+
+// Sends the fee to the fee recipient and the rest to the owner, or to each co-recipient
+// through the token accounts passed in `recipient_accounts`
+#[allow(clippy::too_many_arguments)]
+fn pay_out_tokens<'info>(
+    token_program: &Program<'info, Token>,
+    escrow: &Account<'info, EscrowAccount>,
+    escrow_token_account: &Account<'info, TokenAccount>,
+    mint: &Account<'info, Mint>,
+    owner_token_account: &Account<'info, TokenAccount>,
+    fee_recipient_token_account: &Account<'info, TokenAccount>,
+    recipient_accounts: &'info [AccountInfo<'info>],
+    split: &PayoutSplit,
+) -> Result<()> {
+    if split.fee > 0 {
+        transfer_from_escrow(
+            token_program,
+            escrow,
+            escrow_token_account,
+            mint,
+            fee_recipient_token_account.to_account_info(),
+            split.fee,
+        )?;
+    }
+    if split.shares.is_empty() {
+        return transfer_from_escrow(
+            token_program,
+            escrow,
+            escrow_token_account,
+            mint,
+            owner_token_account.to_account_info(),
+            split.amount,
+        );
+    }
+
+    pay_recipients(token_program, escrow, escrow_token_account, mint, &split.shares, recipient_accounts)
+}
+
+// Pays each co-recipient in `shares` through the token account at the same position in
+// `recipient_accounts`, checking it belongs to the recipient and holds the escrow's mint
+fn pay_recipients<'info>(
+    token_program: &Program<'info, Token>,
+    escrow: &Account<'info, EscrowAccount>,
+    escrow_token_account: &Account<'info, TokenAccount>,
+    mint: &Account<'info, Mint>,
+    shares: &[(Pubkey, u64)],
+    recipient_accounts: &'info [AccountInfo<'info>],
+) -> Result<()> {
+    require!(recipient_accounts.len() == shares.len(), CustomError::WrongRecipientAccount);
+    for ((recipient, share), info) in shares.iter().zip(recipient_accounts) {
+        let token_account: Account<TokenAccount> = Account::try_from(info)?;
+        require_keys_eq!(token_account.owner, *recipient, CustomError::AccountOrderMismatch);
+        require_keys_eq!(token_account.mint, escrow_token_account.mint, CustomError::WrongRecipientAccount);
+        transfer_from_escrow(token_program, escrow, escrow_token_account, mint, info.clone(), *share)?;
+    }
+    Ok(())
+}
+
+// Whether an escrow may sign for `program`: never for the programs that would let the
+// signature move the escrow's tokens or lamports as told
+fn can_sign_for(program: &Pubkey) -> bool {
+    *program != Pubkey::default() && *program != crate::ID && *program != token::ID && *program != system_program::ID
+}
+
+// Calls the yield program's withdraw instruction `data` and books what came back into the
+// vault against the escrow's lent tokens. The vault must not go down.
+fn return_from_yield<'info>(
+    escrow: &mut Account<'info, EscrowAccount>,
+    vault: &mut Account<'info, TokenAccount>,
+    yield_program: &AccountInfo<'info>,
+    accounts: &[AccountInfo<'info>],
+    data: Vec<u8>,
+) -> Result<()> {
+    let before = vault.amount;
+    invoke_yield_program(escrow, yield_program, accounts, data)?;
+    vault.reload()?;
+    let received = vault.amount.checked_sub(before).ok_or(CustomError::YieldMismatch)?;
+    escrow.in_yield = escrow.in_yield.saturating_sub(received);
+    Ok(())
+}
+
+// Calls the group's yield program with `data` over `accounts`, signing as the escrow
+fn invoke_yield_program<'info>(
+    escrow: &Account<'info, EscrowAccount>,
+    yield_program: &AccountInfo<'info>,
+    accounts: &[AccountInfo<'info>],
+    data: Vec<u8>,
+) -> Result<()> {
+    let escrow_key = escrow.key();
+    let metas = accounts
+        .iter()
+        .map(|info| AccountMeta {
+            pubkey: info.key(),
+            is_signer: info.is_signer || info.key() == escrow_key,
+            is_writable: info.is_writable,
+        })
+        .collect();
+    let instruction = Instruction { program_id: yield_program.key(), accounts: metas, data };
+
+    let mut infos = accounts.to_vec();
+    infos.push(yield_program.clone());
+    invoke_signed(&instruction, &infos, &[&escrow.signer_seeds()])?;
+    Ok(())
+}
+
+// Lamport counterpart of pay_out_tokens; co-recipients' wallets are passed in `recipient_accounts`
+fn pay_out_lamports(
+    escrow: &Account<EscrowAccount>,
+    owner: &AccountInfo,
+    fee_recipient: &AccountInfo,
+    recipient_accounts: &[AccountInfo],
+    split: &PayoutSplit,
+) -> Result<()> {
+    // The escrow is program-owned, so lamports are moved directly
+    escrow.sub_lamports(split.fee.checked_add(split.amount).ok_or(CustomError::Overflow)?)?;
+    fee_recipient.add_lamports(split.fee)?;
+    if split.shares.is_empty() {
+        owner.add_lamports(split.amount)?;
+        return Ok(());
+    }
+
+    require!(recipient_accounts.len() == split.shares.len(), CustomError::WrongRecipientAccount);
+    for ((recipient, share), info) in split.shares.iter().zip(recipient_accounts) {
+        require_keys_eq!(info.key(), *recipient, CustomError::AccountOrderMismatch);
+        info.add_lamports(*share)?;
+    }
+    Ok(())
+}
+
+// Sets up a new token escrow of `mint` for the group, held in `vault`
+fn init_token_escrow(
+    group: &mut Account<GroupAccount>,
+    escrow: &mut EscrowAccount,
+    bump: u8,
+    vault: Pubkey,
+    mint: Pubkey,
+) -> Result<()> {
+    require!(!group.is_native, CustomError::WrongAssetType);
+    require!(group.is_active && !group.is_cancelled, CustomError::InactiveGroup);
+    require!(group.accepts_mint(&mint), CustomError::MintNotAllowed);
+    group.escrow_count = group.escrow_count.checked_add(1).ok_or(CustomError::Overflow)?;
+
+    escrow.group = group.key();
+    escrow.total_held = 0;
+    escrow.bump = bump;                        // Canonical bump, used to sign payouts
+    escrow.vault = vault;                      // Only token account the escrow pays from
+    escrow.mint = mint;
+    escrow.paid_cycles = group.cycle_count;    // Payable from the current cycle on
+    escrow.late_fees = 0;
+    escrow.payout_account = Pubkey::default(); // Any of the payout recipient's accounts
+    escrow.paid_cursor = 0;
+    escrow.in_yield = 0;
+    escrow.credit_held = 0;
+    escrow.approvals = 0;
+    escrow.penalties = 0;
+    Ok(())
+}
+
+// Current unix timestamp, from the Clock sysvar, along with the remaining accounts
+#[cfg(not(feature = "test-clock"))]
+fn now<'a, 'info>(remaining_accounts: &'a [AccountInfo<'info>]) -> Result<(i64, &'a [AccountInfo<'info>])> {
+    Ok((Clock::get()?.unix_timestamp, remaining_accounts))
+}
+
+// Test builds run at the TestClock's time when its account is passed as the last
+// remaining account, and hand back the remaining accounts without it
+#[cfg(feature = "test-clock")]
+fn now<'a, 'info>(remaining_accounts: &'a [AccountInfo<'info>]) -> Result<(i64, &'a [AccountInfo<'info>])> {
+    if let Some((last, rest)) = remaining_accounts.split_last() {
+        let (address, _) = Pubkey::find_program_address(&[TEST_CLOCK_SEED], &crate::ID);
+        if last.key() == address && last.owner == &crate::ID {
+            let clock = TestClock::try_deserialize(&mut &last.try_borrow_data()?[..])?;
+            return Ok((clock.unix_timestamp, rest));
+        }
+    }
+    Ok((Clock::get()?.unix_timestamp, remaining_accounts))
+}
+
+// Records a token deposit and moves it into the escrow, or on to the payout recipient for
+// streaming groups. Returns what the member still owes toward their share.
+fn deposit_tokens(ctx: Context<DepositFunds>, amount: u64, memo: [u8; 32]) -> Result<u64> {
+    check_not_paused(&ctx.accounts.config)?;
+    let (now, _) = now(ctx.remaining_accounts)?;
+    require!(!ctx.accounts.group.is_native, CustomError::WrongAssetType);
+    record_deposit(&mut ctx.accounts.group, &mut ctx.accounts.member, &mut ctx.accounts.escrow, amount, now)?;
+
+    // Transfer SPL tokens from member to escrow, checked against the mint's decimals
+    let cpi_accounts = TransferChecked {
+        from: ctx.accounts.from_token_account.to_account_info(),
+        mint: ctx.accounts.mint.to_account_info(),
+        to: ctx.accounts.escrow_token_account.to_account_info(),
+        authority: ctx.accounts.member_authority.to_account_info(),
+    };
+    let cpi_ctx = CpiContext::new(ctx.accounts.token_program.to_account_info(), cpi_accounts);
+    token::transfer_checked(cpi_ctx, amount, ctx.accounts.mint.decimals)?;
+
+    // Streaming groups pass the deposit straight on. It still counts toward total_held
+    // for this cycle's bookkeeping, but the vault no longer holds it.
+    if ctx.accounts.group.stream_payouts {
+        let payout_token_account =
+            ctx.accounts.payout_token_account.as_ref().ok_or(CustomError::WrongPayoutRecipient)?;
+        transfer_from_escrow(
+            &ctx.accounts.token_program,
+            &ctx.accounts.escrow,
+            &ctx.accounts.escrow_token_account,
+            &ctx.accounts.mint,
+            payout_token_account.to_account_info(),
+            amount,
+        )?;
+        let group = &mut ctx.accounts.group;
+        group.streamed_total = group.streamed_total.checked_add(amount).ok_or(CustomError::Overflow)?;
+    }
+
+    emit!(MemberDeposited {
+        group: ctx.accounts.group.key(),
+        member: ctx.accounts.member.member,
+        amount,
+        total_held: ctx.accounts.escrow.total_held,
+        memo,
+        timestamp: now,
+    });
+    ctx.accounts.group.outstanding_of(&ctx.accounts.member)
+}
+
+// Validates a deposit against the group's rules and records it on the member and escrow
+fn record_deposit(
+    group: &mut GroupAccount,
+    member: &mut MemberAccount,
+    escrow: &mut EscrowAccount,
+    amount: u64,
+    now: i64,
+) -> Result<()> {
+    // Ensure group is still active and user hasn't paid yet
+    require!(!group.is_cancelled, CustomError::GroupCancelled);
+    require!(group.is_active, CustomError::InactiveGroup);
+    require!(!group.is_paused, CustomError::GroupPaused);
+    require!(!member.has_paid, CustomError::AlreadyPaid);
+    // Later pages of a paged payout split what the escrow held when the first one ran
+    require!(!group.payout_in_progress, CustomError::PayoutInProgress);
+
+    // Late deposits are accepted until the grace period after the member's deadline runs out
+    let due = member.due(group);
+    require!(now <= grace_end_of(due, group.grace_period)?, CustomError::DepositWindowClosed);
+    // The owner's deposit deadline closes collection early, except for members the owner
+    // granted a later personal deadline
+    require!(
+        group.deposit_deadline == 0 || now <= group.deposit_deadline || member.member_due > group.subscription_due,
+        CustomError::DepositDeadlinePassed
+    );
+
+    // A member pays their whole share in one mint, so a refund comes from a single escrow.
+    // Credit stays in the escrow it was paid into, so it ties the member to that mint too.
+    if !member.has_funds() {
+        member.deposit_mint = escrow.mint;
+    }
+    require_keys_eq!(member.deposit_mint, escrow.mint, CustomError::MixedMints);
+    require!(group.accepts_mint(&escrow.mint), CustomError::MintNotAllowed);
+
+    // Deposits may be split into installments but never exceed the member's share.
+    // After the due time, the rest of the share must be paid at once with the late
+    // surcharge on top. The first deposit pins the share, so later joins can't move it.
+    group.pin(member)?;
+    let share = member.owed;
+    // Credit carried over from an earlier cycle covers the share before new tokens do, so
+    // a member with enough credit deposits nothing
+    let applied = member.credit.min(share.saturating_sub(member.contributed));
+    let covered = member.contributed.checked_add(applied).ok_or(CustomError::Overflow)?;
+    require!(amount > 0 || applied > 0, CustomError::WrongAmount);
+    let late = now > due;
+    let (paid, surcharge, surplus) = if late {
+        let remaining = share.saturating_sub(covered);
+        let surcharge = group.late_fee_for(remaining);
+        let required = remaining.checked_add(surcharge).ok_or(CustomError::Overflow)?;
+        require!(amount >= required, CustomError::LateFeeRequired);
+        require!(amount == required, CustomError::ExceedsShare);
+        (remaining, surcharge, 0)
+    } else if group.allow_credit {
+        // Anything past the share is kept as credit toward the next cycle
+        let paid = amount.min(share.saturating_sub(covered));
+        (paid, 0, amount - paid)
+    } else {
+        (amount, 0, 0)
+    };
+    let contributed = covered.checked_add(paid).ok_or(CustomError::Overflow)?;
+    require!(contributed <= share, CustomError::ExceedsShare);
+    require!(amount >= group.min_deposit || contributed == share, CustomError::DepositTooSmall);
+    require!(
+        group.max_per_member == 0 || contributed <= group.max_per_member,
+        CustomError::PerMemberCapExceeded
+    );
+
+    // Shares grow or shrink as members come and go, so also cap what the group holds, over
+    // every escrow, at the cost. Late surcharges and refund penalties are collected on top.
+    let held = (amount - surplus).checked_add(applied).ok_or(CustomError::Overflow)?;
+    let group_held = group.total_held.checked_add(held).ok_or(CustomError::Overflow)?;
+    let fees_held = group.fees_held.checked_add(surcharge).ok_or(CustomError::Overflow)?;
+    require!(group_held - fees_held <= group.total_cost, CustomError::ExceedsCost);
+
+    // Record contribution in member account, marking it paid once the share is covered
+    if member.first_deposit_at == 0 {
+        member.first_deposit_at = now;
+    }
+    member.contributed = contributed;
+    member.has_paid = contributed >= share;
+    if member.has_paid {
+        group.paid_count = group.paid_count.checked_add(1).ok_or(CustomError::Overflow)?;
+        member.cycles_paid = member.cycles_paid.checked_add(1).ok_or(CustomError::Overflow)?;
+    }
+    member.total_contributed_lifetime =
+        member.total_contributed_lifetime.checked_add(held).ok_or(CustomError::Overflow)?;
+    member.paid_late |= late;
+    member.late_fee_paid = member.late_fee_paid.checked_add(surcharge).ok_or(CustomError::Overflow)?;
+    member.credit = (member.credit - applied).checked_add(surplus).ok_or(CustomError::Overflow)?;
+    escrow.total_held = escrow.total_held.checked_add(held).ok_or(CustomError::Overflow)?;
+    escrow.late_fees = escrow.late_fees.checked_add(surcharge).ok_or(CustomError::Overflow)?;
+    escrow.credit_held = (escrow.credit_held - applied).checked_add(surplus).ok_or(CustomError::Overflow)?;
+    group.total_held = group_held;
+    group.fees_held = fees_held;
+    Ok(())
+}
+
+// Validates a refund and clears the member's contribution, returning the amount to send back.
+// Refunds the member asks for before the due time (`penalize`) leave the group's refund
+// penalty in the escrow, to be paid out with it; cancelled groups refund in full.
+fn record_refund(
+    group: &mut GroupAccount,
+    member: &mut MemberAccount,
+    escrow: &mut EscrowAccount,
+    penalize: bool,
+    now: i64,
+) -> Result<u64> {
+    // Refunds are only possible before the subscription is paid out,
+    // or at any time once the group has been cancelled
+    if group.is_cancelled {
+        return clear_contribution(group, member, escrow);
+    }
+    // A paid-out group holds nothing for the member but credit, which would otherwise be
+    // stranded, and keep the group from closing, if the group is never renewed
+    if !group.is_active {
+        return release_credit(member, escrow);
+    }
+    require!(now < group.subscription_due, CustomError::TooLate);
+
+    let penalty = if penalize { group.refund_penalty_for(member.contributed) } else { 0 };
+    let amount = clear_contribution(group, member, escrow)?;
+    escrow.penalties = escrow.penalties.checked_add(penalty).ok_or(CustomError::Overflow)?;
+    escrow.total_held = escrow.total_held.checked_add(penalty).ok_or(CustomError::Overflow)?;
+    group.total_held = group.total_held.checked_add(penalty).ok_or(CustomError::Overflow)?;
+    group.fees_held = group.fees_held.checked_add(penalty).ok_or(CustomError::Overflow)?;
+    Ok(amount - penalty)
+}
+
+// Clears the member's unspent credit from the escrow, returning the amount to send back
+fn release_credit(member: &mut MemberAccount, escrow: &mut EscrowAccount) -> Result<u64> {
+    require!(member.credit > 0, CustomError::NothingToRefund);
+    require_keys_eq!(member.deposit_mint, escrow.mint, CustomError::WrongMint);
+    require!(escrow.in_yield == 0, CustomError::YieldOutstanding);
+    escrow.credit_held = escrow.credit_held.checked_sub(member.credit).ok_or(CustomError::Overflow)?;
+    Ok(std::mem::take(&mut member.credit))
+}
+
+// Clears the member's contribution from the group and escrow, returning the amount to send back
+fn clear_contribution(group: &mut GroupAccount, member: &mut MemberAccount, escrow: &mut EscrowAccount) -> Result<u64> {
+    require!(member.has_funds(), CustomError::NothingToRefund);
+    require!(!group.stream_payouts, CustomError::FundsStreamed);
+    // Part of a paged payout is already out of the vault, so full refunds would overdraw it
+    require!(!group.payout_in_progress, CustomError::PayoutInProgress);
+    require_keys_eq!(member.deposit_mint, escrow.mint, CustomError::WrongMint);
+    require!(escrow.in_yield == 0, CustomError::YieldOutstanding);
+
+    // Any late surcharge is returned along with the share
+    let amount = member.contributed.checked_add(member.late_fee_paid).ok_or(CustomError::Overflow)?;
+    if member.has_paid {
+        group.paid_count = group.paid_count.checked_sub(1).ok_or(CustomError::Overflow)?;
+        member.cycles_paid = member.cycles_paid.checked_sub(1).ok_or(CustomError::Overflow)?;
+    }
+
+    // A refunded contribution no longer counts toward the member's history
+    member.total_contributed_lifetime =
+        member.total_contributed_lifetime.checked_sub(amount).ok_or(CustomError::Overflow)?;
+
+    escrow.late_fees = escrow.late_fees.checked_sub(member.late_fee_paid).ok_or(CustomError::Overflow)?;
+    group.fees_held = group.fees_held.checked_sub(member.late_fee_paid).ok_or(CustomError::Overflow)?;
+    member.contributed = 0;
+    group.unpin(member)?;
+    member.has_paid = false;
+    member.late_fee_paid = 0;
+    member.first_deposit_at = 0;
+    escrow.total_held = escrow.total_held.checked_sub(amount).ok_or(CustomError::Overflow)?;
+    group.total_held = group.total_held.checked_sub(amount).ok_or(CustomError::Overflow)?;
+
+    // Unspent credit goes back too; it was never part of total_held
+    escrow.credit_held = escrow.credit_held.checked_sub(member.credit).ok_or(CustomError::Overflow)?;
+    let amount = amount.checked_add(member.credit).ok_or(CustomError::Overflow)?;
+    member.credit = 0;
+    Ok(amount)
+}
+
+// Ensures the group can be paid out: not cancelled, paid out or disputed, with enough members
+// and its grace period over
+fn check_payout(group: &GroupAccount, now: i64) -> Result<()> {
+    require!(!group.is_cancelled, CustomError::GroupCancelled);
+    require!(group.is_active, CustomError::AlreadyPaidOut);
+    require!(!group.disputed, CustomError::Disputed);
+    require!(now >= group.payout_opens_at(), CustomError::TooEarly);
+    require!(group.member_count >= group.min_members, CustomError::TooFewMembers);
+    // With a single paying member that member could be the payout recipient, splitting
+    // the bill with nobody
+    require!(!group.anti_self_split || group.paid_count >= 2, CustomError::SelfSplit);
+    // An empty cycle is normally just closed, with nothing transferred
+    require!(
+        !group.reject_empty_payout || group.total_held > 0 || group.escrows_paid > 0,
+        CustomError::NothingToPayOut
+    );
+    require!(
+        group.allow_partial || group.paid_count == group.member_count,
+        CustomError::NotFullyFunded
+    );
+    Ok(())
+}
+
+// Payouts are permissionless unless the group restricts them to its owner, in which case
+// the owner must sign as the settler
+fn check_settler(group: &GroupAccount, settler: &Option<Signer>) -> Result<()> {
+    if group.restrict_payout {
+        let settler = settler.as_ref().ok_or(CustomError::Unauthorized)?;
+        require_keys_eq!(settler.key(), group.owner, CustomError::Unauthorized);
+    }
+    Ok(())
+}
+
+// Ensures the grace period is over for a group that is still underfunded and was never paid out
+fn check_expired_refund(group: &GroupAccount, now: i64) -> Result<()> {
+    require!(!group.is_cancelled, CustomError::GroupCancelled);
+    require!(group.is_active, CustomError::AlreadyPaidOut);
+    require!(now > group.grace_end(), CustomError::TooEarly);
+    require!(group.paid_count < group.member_count, CustomError::GroupFunded);
+    Ok(())
+}
+
+// Ensures the group can still be paid out, is still underfunded, and the emergency delay
+// after the grace period has passed; a fully funded group is paid out the normal way
+fn check_emergency_withdraw(group: &GroupAccount, now: i64) -> Result<()> {
+    require!(!group.is_cancelled, CustomError::GroupCancelled);
+    require!(group.is_active, CustomError::AlreadyPaidOut);
+    require!(!group.disputed, CustomError::Disputed);
+    require!(group.paid_count < group.member_count, CustomError::GroupFunded);
+    require!(now >= group.grace_end().saturating_add(EMERGENCY_DELAY), CustomError::TooEarly);
+    Ok(())
+}
+
+// Records an escrow's payout. The billing cycle is over, and the group stops collecting,
+// once every escrow of the group has been paid out.
+fn settle_payout(group: &mut GroupAccount, escrow: &mut EscrowAccount, now: i64) -> Result<()> {
+    require!(escrow.paid_cycles == group.cycle_count, CustomError::AlreadyPaidOut);
+    require!(escrow.in_yield == 0, CustomError::YieldOutstanding);
+    group.total_held = group.total_held.checked_sub(escrow.total_held).ok_or(CustomError::Overflow)?;
+    let fees = escrow.late_fees.checked_add(escrow.penalties).ok_or(CustomError::Overflow)?;
+    group.fees_held = group.fees_held.checked_sub(fees).ok_or(CustomError::Overflow)?;
+    escrow.total_held = 0;
+    escrow.late_fees = 0;
+    escrow.penalties = 0;
+    escrow.approvals = 0;
+    escrow.paid_cycles = escrow.paid_cycles.checked_add(1).ok_or(CustomError::Overflow)?;
+
+    group.escrows_paid = group.escrows_paid.checked_add(1).ok_or(CustomError::Overflow)?;
+    if group.escrows_paid == group.escrow_count {
+        group.escrows_paid = 0;
+        group.is_active = false; // Mark group as completed/inactive
+        group.paid_out_at = now;
+        group.cycle_count = group.cycle_count.checked_add(1).ok_or(CustomError::Overflow)?;
+    }
+    Ok(())
+}
+
+// Context for setting the test clock; whoever creates it pays its rent
+#[cfg(feature = "test-clock")]
+#[derive(Accounts)]
+pub struct SetTestClock<'info> {
+    #[account(
+        init_if_needed,
+        payer = payer,
+        space = TestClock::SPACE,
+        seeds = [TEST_CLOCK_SEED],
+        bump
+    )]
+    pub test_clock: Account<'info, TestClock>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+// Time override read by `now` in test builds
+#[cfg(feature = "test-clock")]
+#[account]
+pub struct TestClock {
+    pub unix_timestamp: i64,
+}
+
+#[cfg(feature = "test-clock")]
+impl TestClock {
+    pub const SPACE: usize = 8 // Discriminator
+        + 8;                   // unix_timestamp
+}
+
+// Context for creating the program config
+#[derive(Accounts)]
+pub struct InitializeConfig<'info> {
+    #[account(init, payer = admin, space = ProgramConfig::SPACE, seeds = [CONFIG_SEED], bump)]
+    pub config: Account<'info, ProgramConfig>,
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    #[account(constraint = program.programdata_address()? == Some(program_data.key()) @ CustomError::Unauthorized)]
+    pub program: Program<'info, crate::program::SplitFunds>,
+    #[account(constraint = program_data.upgrade_authority_address == Some(admin.key()) @ CustomError::Unauthorized)]
+    pub program_data: Account<'info, ProgramData>, // This program's, naming its upgrade authority
+    pub system_program: Program<'info, System>,
+}
+
+// Context for the admin updating the program config
+#[derive(Accounts)]
+pub struct UpdateConfig<'info> {
+    #[account(mut, seeds = [CONFIG_SEED], bump = config.bump, has_one = admin @ CustomError::Unauthorized)]
+    pub config: Account<'info, ProgramConfig>,
+    pub admin: Signer<'info>,
+}
+
+// Program-wide defaults and caps, as set by the config admin
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct ConfigParams {
+    pub default_fee_bps: u16,  // Fee of groups created without one, in basis points
+    pub max_total_cost: u64,   // Highest total cost a group may have; 0 for MAX_TOTAL_COST
+    pub anti_self_split: bool, // Reject groups a single wallet could pay out to itself
+}
+
+impl ConfigParams {
+    pub fn validate(&self) -> Result<()> {
+        require!(u64::from(self.default_fee_bps) <= BPS_DENOMINATOR, CustomError::InvalidFee);
+        require!(self.max_total_cost <= MAX_TOTAL_COST, CustomError::CostTooHigh);
+        Ok(())
+    }
+}
+
+// Configuration supplied when creating a group
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct CreateGroupParams {
+    pub group_name: String,
+    pub total_cost: u64,                  // Total subscription cost
+    pub subscription_due: i64,            // Subscription due time (timestamp)
+    pub is_native: bool,                  // Collect SOL instead of an SPL token
+    pub grace_period: i64,                // Seconds after the due time late deposits are accepted
+    pub allow_partial: bool,              // Pay out without waiting for every member
+    pub fee_bps: Option<u16>,             // Platform fee taken at payout, in basis points; None for the default
+    pub fee_recipient: Pubkey,            // Wallet receiving the platform fee
+    pub min_members: u8,                  // Members required before payout
+    pub max_members: u8,                  // Seats available in the group
+    pub recipients: Vec<Recipient>,       // Co-owners splitting the payout; empty pays the owner
+    pub payout_recipient: Option<Pubkey>, // E.g. the merchant; defaults to the owner
+    pub late_fee_bps: u16,                // Surcharge on deposits after the due time, in basis points
+    pub invite_only: bool,                // Only the owner can let wallets join
+    pub max_per_member: u64,              // Most one member may contribute per cycle; 0 for no cap
+    pub allow_late_join: bool,            // Keep accepting members after the first deposit
+    pub stream_payouts: bool,             // Forward each deposit to the payout recipient at once
+    pub min_deposit: u64,                 // Smallest installment, unless it completes the share
+    pub category: u8,                     // A GroupCategory discriminant
+    pub yield_enabled: bool,              // Let the owner put escrowed tokens to work
+    pub yield_program: Pubkey,            // Lending program used when yield_enabled
+    pub allowed_mints: Vec<Pubkey>,       // Stablecoins the group takes; empty takes any mint
+    pub allow_credit: bool,               // Keep overpayments as credit toward the next cycle
+    pub restrict_payout: bool,            // Only the owner may execute payouts
+    pub symbol: [u8; SYMBOL_LEN],         // Zero-padded currency symbol for front-ends
+    pub decimals_hint: u8,                // Decimals front-ends should display amounts with
+    pub approvers: Vec<Pubkey>,           // Up to MAX_APPROVERS wallets that approve large payouts
+    pub threshold: u8,                    // Approvals needed, from 1 to approvers.len(); 0 without approvers
+    pub approval_above: u64,              // Payouts of escrows holding more than this need approvals
+    pub settlement_delay: i64,            // Seconds payout waits after the grace period; 0 for none
+    pub nonce: u64,                       // The owner's next nonce, from their OwnerCounter
+    pub refund_penalty_bps: u16,          // Part of a contribution kept when its member refunds early
+    pub deposit_deadline: i64,            // Last time deposits are taken, at most the due time; 0 for none
+    pub reject_empty_payout: bool,        // Fail payouts of a cycle nobody paid into instead of closing it
+}
+
+// What a group pays for, stored on the group as its u8 discriminant so front-ends and
+// indexers can filter groups
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[repr(u8)]
+pub enum GroupCategory {
+    Other,
+    Streaming,
+    Music,
+    Gym,
+    Software,
+    Gaming,
+}
+
+impl GroupCategory {
+    // Number of categories; valid discriminants are below it
+    pub const COUNT: u8 = GroupCategory::Gaming as u8 + 1;
+}
+
+// A co-owner's cut of every payout
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct Recipient {
+    pub pubkey: Pubkey, // Wallet receiving the cut
+    pub bps: u16,       // Share of the payout after fees, in basis points
+}
+
+impl Recipient {
+    pub const SPACE: usize = 32 // pubkey
+        + 2;                    // bps
+}
+
+// A payout divided between the platform fee and the owner or co-recipients
+pub struct PayoutSplit {
+    pub fee: u64,                   // Paid to the fee recipient
+    pub amount: u64,                // Everything after the fee
+    pub shares: Vec<(Pubkey, u64)>, // Per co-recipient amounts; empty when the owner takes `amount`
+}
+
+// Context for creating a group
+#[derive(Accounts)]
+#[instruction(params: CreateGroupParams)]
+pub struct CreateGroup<'info> {
+    #[account(
+        init,
+        payer = owner,
+        space = GroupAccount::space_for(&params.group_name),
+        seeds = [
+            GROUP_SEED,
+            owner.key().as_ref(),
+            group_name_seeds(&params.group_name)[0],
+            group_name_seeds(&params.group_name)[1],
+            &params.nonce.to_le_bytes(),
+        ],
+        bump
+    )]
+    pub group: Account<'info, GroupAccount>,
+    #[account(
+        init_if_needed,
+        payer = owner,
+        space = OwnerCounter::SPACE,
+        seeds = [OWNER_COUNTER_SEED, owner.key().as_ref()],
+        bump
+    )]
+    pub owner_counter: Account<'info, OwnerCounter>,
+    pub mint: Option<Account<'info, Mint>>, // Token the group collects; None for native SOL
+    /// CHECK: the ProgramConfig PDA, which may not be initialized; read by load_config
+    #[account(seeds = [CONFIG_SEED], bump)]
+    pub config: UncheckedAccount<'info>,
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+// Context for inviting a member
+#[derive(Accounts)]
+pub struct InviteMember<'info> {
+    #[account(mut)]
+    pub group: Account<'info, GroupAccount>,
+    // One membership per wallet per group: a second invite hits an existing account.
+    // These seeds must stay in step with member_pda.
+    #[account(
+        init,
+        payer = payer,
+        space = MemberAccount::SPACE,
+        seeds = [group.key().as_ref(), member_authority.key().as_ref()],
+        bump
+    )]
+    pub member: Account<'info, MemberAccount>,
+    /// CHECK: the invited wallet; it must sign unless the group's owner does
+    pub member_authority: UncheckedAccount<'info>,
+    pub owner: Option<Signer<'info>>, // Required by invite-only groups
+    #[account(mut)]
+    pub payer: Signer<'info>,         // Pays the membership's rent; the member or a sponsor
+    /// CHECK: the ProgramConfig PDA, which may not be initialized; read by load_config
+    #[account(seeds = [CONFIG_SEED], bump)]
+    pub config: UncheckedAccount<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+// Context for the owner inviting several members; membership PDAs are remaining accounts
+#[derive(Accounts)]
+pub struct BatchInvite<'info> {
+    #[account(mut, has_one = owner)]
+    pub group: Account<'info, GroupAccount>,
+    #[account(mut)]
+    pub owner: Signer<'info>, // Pays rent for every new membership
+    /// CHECK: the ProgramConfig PDA, which may not be initialized; read by load_config
+    #[account(seeds = [CONFIG_SEED], bump)]
+    pub config: UncheckedAccount<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+// Context for initializing a group's escrow
+#[derive(Accounts)]
+pub struct InitializeEscrow<'info> {
+    #[account(mut, has_one = owner)]
+    pub group: Account<'info, GroupAccount>,
+    #[account(
+        init,
+        payer = owner,
+        seeds = [group.key().as_ref(), mint.key().as_ref()],
+        bump,
+        space = EscrowAccount::SPACE
+    )]
+    pub escrow: Account<'info, EscrowAccount>,
+    pub mint: Account<'info, Mint>, // A mint the owner chooses to accept, from the allowlist if any
+    #[account(
+        init,
+        payer = owner,
+        seeds = [VAULT_SEED, group.key().as_ref(), mint.key().as_ref()],
+        bump,
+        token::mint = mint,
+        token::authority = escrow
+    )]
+    pub escrow_token_account: Account<'info, TokenAccount>, // Vault PDA owned by the escrow PDA
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+// Context for initialize_escrow_if_needed; the same accounts as InitializeEscrow, created
+// only when missing
+#[derive(Accounts)]
+pub struct InitializeEscrowIfNeeded<'info> {
+    #[account(mut, has_one = owner)]
+    pub group: Account<'info, GroupAccount>,
+    #[account(
+        init_if_needed,
+        payer = owner,
+        seeds = [group.key().as_ref(), mint.key().as_ref()],
+        bump,
+        space = EscrowAccount::SPACE
+    )]
+    pub escrow: Account<'info, EscrowAccount>,
+    pub mint: Account<'info, Mint>, // A mint the owner chooses to accept, from the allowlist if any
+    #[account(
+        init_if_needed,
+        payer = owner,
+        seeds = [VAULT_SEED, group.key().as_ref(), mint.key().as_ref()],
+        bump,
+        token::mint = mint,
+        token::authority = escrow
+    )]
+    pub escrow_token_account: Account<'info, TokenAccount>, // Vault PDA owned by the escrow PDA
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+// Context for initializing a native SOL group's escrow
+#[derive(Accounts)]
+pub struct InitializeSolEscrow<'info> {
+    #[account(mut, has_one = owner)]
+    pub group: Account<'info, GroupAccount>,
+    #[account(
+        init,
+        payer = owner,
+        seeds = [group.key().as_ref(), group.mint.as_ref()],
+        bump,
+        space = EscrowAccount::SPACE
+    )]
+    pub escrow: Account<'info, EscrowAccount>,
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+// Context for depositing funds into the escrow
+#[derive(Accounts)]
+pub struct DepositFunds<'info> {
+    #[account(mut)]
+    pub group: Account<'info, GroupAccount>,
+    #[account(
+        mut,
+        has_one = group,
+        constraint = member.member == member_authority.key() @ CustomError::Unauthorized
+    )]
+    pub member: Account<'info, MemberAccount>,
+    #[account(mut)]
+    pub member_authority: Signer<'info>,
+    #[account(mut, has_one = group)]
+    pub escrow: Account<'info, EscrowAccount>, // Escrow of the mint being deposited
+    #[account(address = escrow.mint @ CustomError::WrongMint)]
+    pub mint: Account<'info, Mint>,
+    #[account(mut, token::mint = mint)]
+    pub from_token_account: Account<'info, TokenAccount>, // Member's token account
+    #[account(mut, address = escrow.vault @ CustomError::WrongVault)]
+    pub escrow_token_account: Account<'info, TokenAccount>, // Escrow's token vault
+    #[account(
+        mut,
+        token::mint = mint,
+        constraint = payout_token_account.owner == group.payout_recipient @ CustomError::WrongPayoutRecipient,
+        constraint = escrow.pays_to(&payout_token_account.key()) @ CustomError::WrongPayoutAccount
+    )]
+    pub payout_token_account: Option<Account<'info, TokenAccount>>, // Required by streaming groups
+    /// CHECK: the ProgramConfig PDA, which may not be initialized; read by load_config
+    #[account(seeds = [CONFIG_SEED], bump)]
+    pub config: UncheckedAccount<'info>,
+    pub token_program: Program<'info, Token>,
+}
+
+// Context for depositing lamports into a native SOL group's escrow
+#[derive(Accounts)]
+pub struct DepositSol<'info> {
+    #[account(mut)]
+    pub group: Account<'info, GroupAccount>,
+    #[account(
+        mut,
+        has_one = group,
+        constraint = member.member == member_authority.key() @ CustomError::Unauthorized
+    )]
+    pub member: Account<'info, MemberAccount>,
+    #[account(mut)]
+    pub member_authority: Signer<'info>,
+    #[account(mut, has_one = group)]
+    pub escrow: Account<'info, EscrowAccount>,
+    /// CHECK: the ProgramConfig PDA, which may not be initialized; read by load_config
+    #[account(seeds = [CONFIG_SEED], bump)]
+    pub config: UncheckedAccount<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+// Context for refunding a member's deposit
+#[derive(Accounts)]
+pub struct RefundMember<'info> {
+    #[account(mut)]
+    pub group: Account<'info, GroupAccount>,
+    #[account(
+        mut,
+        has_one = group,
+        constraint = member.member == member_authority.key() @ CustomError::Unauthorized
+    )]
+    pub member: Account<'info, MemberAccount>,
+    pub member_authority: Signer<'info>,
+    #[account(mut, has_one = group)]
+    pub escrow: Account<'info, EscrowAccount>, // Escrow of the mint the member paid in
+    #[account(address = escrow.mint @ CustomError::WrongMint)]
+    pub mint: Account<'info, Mint>,
+    #[account(mut, token::mint = mint, token::authority = member_authority)]
+    pub member_token_account: Account<'info, TokenAccount>, // Receives the refund
+    #[account(mut, address = escrow.vault @ CustomError::WrongVault)]
+    pub escrow_token_account: Account<'info, TokenAccount>, // Escrow's token vault
+    pub token_program: Program<'info, Token>,
+}
+
+// Context for refunding a member's lamports from a native SOL group
+#[derive(Accounts)]
+pub struct RefundMemberSol<'info> {
+    #[account(mut)]
+    pub group: Account<'info, GroupAccount>,
+    #[account(
+        mut,
+        has_one = group,
+        constraint = member.member == member_authority.key() @ CustomError::Unauthorized
+    )]
+    pub member: Account<'info, MemberAccount>,
+    #[account(mut)]
+    pub member_authority: Signer<'info>,
+    #[account(mut, has_one = group)]
+    pub escrow: Account<'info, EscrowAccount>,
+}
+
+// Context for refunding the members of a cancelled group; member and token accounts are
+// passed as remaining accounts
+#[derive(Accounts)]
+pub struct RefundAll<'info> {
+    #[account(mut, has_one = owner)]
+    pub group: Account<'info, GroupAccount>,
+    #[account(mut, has_one = group)]
+    pub escrow: Account<'info, EscrowAccount>,
+    #[account(address = escrow.mint @ CustomError::WrongMint)]
+    pub mint: Account<'info, Mint>,
+    #[account(mut, address = escrow.vault @ CustomError::WrongVault)]
+    pub escrow_token_account: Account<'info, TokenAccount>, // Escrow's token vault
+    pub owner: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+}
+
+// Context for returning a member's contribution from an expired group
+#[derive(Accounts)]
+pub struct ClaimExpiredRefund<'info> {
+    #[account(mut)]
+    pub group: Account<'info, GroupAccount>,
+    #[account(mut, has_one = group)]
+    pub member: Account<'info, MemberAccount>,
+    #[account(mut, has_one = group)]
+    pub escrow: Account<'info, EscrowAccount>, // Escrow of the mint the member paid in
+    #[account(address = escrow.mint @ CustomError::WrongMint)]
+    pub mint: Account<'info, Mint>,
+    #[account(
+        mut,
+        token::mint = mint,
+        constraint = member_token_account.owner == member.member @ CustomError::Unauthorized
+    )]
+    pub member_token_account: Account<'info, TokenAccount>, // Receives the refund
+    #[account(mut, address = escrow.vault @ CustomError::WrongVault)]
+    pub escrow_token_account: Account<'info, TokenAccount>, // Escrow's token vault
+    pub token_program: Program<'info, Token>,
+}
+
+// Context for returning a member's lamports from an expired native SOL group
+#[derive(Accounts)]
+pub struct ClaimExpiredRefundSol<'info> {
+    #[account(mut)]
+    pub group: Account<'info, GroupAccount>,
+    #[account(mut, has_one = group)]
+    pub member: Account<'info, MemberAccount>,
+    #[account(mut, address = member.member @ CustomError::Unauthorized)]
+    pub member_authority: SystemAccount<'info>, // Receives the refund
+    #[account(mut, has_one = group)]
+    pub escrow: Account<'info, EscrowAccount>,
+}
+
+// Context for leaving a group; the member account is closed to the member
+#[derive(Accounts)]
+pub struct LeaveGroup<'info> {
+    #[account(mut)]
+    pub group: Account<'info, GroupAccount>,
+    #[account(
+        mut,
+        has_one = group,
+        close = member_authority,
+        constraint = member.member == member_authority.key() @ CustomError::Unauthorized
+    )]
+    pub member: Account<'info, MemberAccount>,
+    #[account(mut)]
+    pub member_authority: Signer<'info>,
+    #[account(mut, has_one = group)]
+    pub escrow: Account<'info, EscrowAccount>, // Escrow of the mint the member paid in
+    #[account(address = escrow.mint @ CustomError::WrongMint)]
+    pub mint: Account<'info, Mint>,
+    #[account(mut, token::mint = mint, token::authority = member_authority)]
+    pub member_token_account: Account<'info, TokenAccount>, // Receives the refund
+    #[account(mut, address = escrow.vault @ CustomError::WrongVault)]
+    pub escrow_token_account: Account<'info, TokenAccount>, // Escrow's token vault
+    pub token_program: Program<'info, Token>,
+}
+
+// Context for the owner removing a member; the membership closes to the member's wallet
+#[derive(Accounts)]
+pub struct KickMember<'info> {
+    #[account(mut, has_one = owner)]
+    pub group: Account<'info, GroupAccount>,
+    pub owner: Signer<'info>,
+    #[account(mut, has_one = group, close = member_wallet)]
+    pub member: Account<'info, MemberAccount>,
+    #[account(mut, address = member.member @ CustomError::Unauthorized)]
+    pub member_wallet: SystemAccount<'info>, // Gets the membership's rent back
+    #[account(mut, has_one = group)]
+    pub escrow: Account<'info, EscrowAccount>, // Escrow of the mint the member paid in
+    #[account(address = escrow.mint @ CustomError::WrongMint)]
+    pub mint: Account<'info, Mint>,
+    #[account(mut, token::mint = mint, token::authority = member_wallet)]
+    pub member_token_account: Account<'info, TokenAccount>, // Receives the refund
+    #[account(mut, address = escrow.vault @ CustomError::WrongVault)]
+    pub escrow_token_account: Account<'info, TokenAccount>, // Escrow's token vault
+    pub token_program: Program<'info, Token>,
+}
+
+// Context for an approver signing off on an escrow's payout
+#[derive(Accounts)]
+pub struct ApprovePayout<'info> {
+    pub group: Account<'info, GroupAccount>,
+    #[account(mut, has_one = group)]
+    pub escrow: Account<'info, EscrowAccount>,
+    pub approver: Signer<'info>,
+}
+
+// Context for executing payout to group owner
+#[derive(Accounts)]
+pub struct ExecutePayout<'info> {
+    #[account(mut)]
+    pub group: Account<'info, GroupAccount>,
+    #[account(mut, has_one = group)]
+    pub escrow: Account<'info, EscrowAccount>,
+    #[account(address = escrow.mint @ CustomError::WrongMint)]
+    pub mint: Account<'info, Mint>,
+    #[account(mut, address = escrow.vault @ CustomError::WrongVault)]
+    pub escrow_token_account: Account<'info, TokenAccount>, // Escrow's token vault
+    #[account(
+        mut,
+        token::mint = mint,
+        constraint = owner_token_account.owner == group.payout_recipient @ CustomError::WrongPayoutRecipient,
+        constraint = escrow.pays_to(&owner_token_account.key()) @ CustomError::WrongPayoutAccount
+    )]
+    pub owner_token_account: Account<'info, TokenAccount>,  // Payout recipient's token account
+    #[account(mut, token::mint = mint, token::authority = group.fee_recipient)]
+    pub fee_recipient_token_account: Account<'info, TokenAccount>, // Receives the platform fee
+    pub settler: Option<Signer<'info>>, // The owner, for groups with restrict_payout
+    pub token_program: Program<'info, Token>,
+}
+
+// Context for paying one page of a co-recipient payout; recipients' token accounts are
+// passed as remaining accounts
+#[derive(Accounts)]
+pub struct ExecutePayoutPage<'info> {
+    #[account(mut)]
+    pub group: Account<'info, GroupAccount>,
+    #[account(mut, has_one = group)]
+    pub escrow: Account<'info, EscrowAccount>,
+    #[account(address = escrow.mint @ CustomError::WrongMint)]
+    pub mint: Account<'info, Mint>,
+    #[account(mut, address = escrow.vault @ CustomError::WrongVault)]
+    pub escrow_token_account: Account<'info, TokenAccount>, // Escrow's token vault
+    #[account(mut, token::mint = mint, token::authority = group.fee_recipient)]
+    pub fee_recipient_token_account: Account<'info, TokenAccount>, // Receives the platform fee
+    pub settler: Option<Signer<'info>>, // The owner, for groups with restrict_payout
+    pub token_program: Program<'info, Token>,
+}
+
+// Context for executing payout of a native SOL group
+#[derive(Accounts)]
+pub struct ExecutePayoutSol<'info> {
+    #[account(mut)]
+    pub group: Account<'info, GroupAccount>,
+    #[account(mut, has_one = group)]
+    pub escrow: Account<'info, EscrowAccount>,
+    #[account(mut, address = group.payout_recipient @ CustomError::WrongPayoutRecipient)]
+    pub payout_recipient: SystemAccount<'info>, // Receives the lamports
+    #[account(mut, address = group.fee_recipient)]
+    pub fee_recipient: SystemAccount<'info>,    // Receives the platform fee
+    pub settler: Option<Signer<'info>>,         // The owner, for groups with restrict_payout
+}
+
+// Context for the owner sweeping an underfunded token group
+#[derive(Accounts)]
+pub struct EmergencyWithdraw<'info> {
+    #[account(mut, has_one = owner)]
+    pub group: Account<'info, GroupAccount>,
+    #[account(mut, has_one = group)]
+    pub escrow: Account<'info, EscrowAccount>,
+    #[account(address = escrow.mint @ CustomError::WrongMint)]
+    pub mint: Account<'info, Mint>,
+    #[account(mut, address = escrow.vault @ CustomError::WrongVault)]
+    pub escrow_token_account: Account<'info, TokenAccount>, // Escrow's token vault
+    #[account(
+        mut,
+        token::mint = mint,
+        constraint = owner_token_account.owner == group.payout_recipient @ CustomError::WrongPayoutRecipient,
+        constraint = escrow.pays_to(&owner_token_account.key()) @ CustomError::WrongPayoutAccount
+    )]
+    pub owner_token_account: Account<'info, TokenAccount>,  // Payout recipient's token account
+    #[account(mut, token::mint = mint, token::authority = group.fee_recipient)]
+    pub fee_recipient_token_account: Account<'info, TokenAccount>, // Receives the platform fee
+    pub owner: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+}
+
+// Context for the owner sweeping an underfunded native SOL group
+#[derive(Accounts)]
+pub struct EmergencyWithdrawSol<'info> {
+    #[account(mut, has_one = owner)]
+    pub group: Account<'info, GroupAccount>,
+    #[account(mut, has_one = group)]
+    pub escrow: Account<'info, EscrowAccount>,
+    pub owner: Signer<'info>,
+    #[account(mut, address = group.payout_recipient @ CustomError::WrongPayoutRecipient)]
+    pub payout_recipient: SystemAccount<'info>, // Receives the lamports
+    #[account(mut, address = group.fee_recipient)]
+    pub fee_recipient: SystemAccount<'info>, // Receives the platform fee
+}
+
+// Context for the owner reclaiming untracked tokens from the vault
+#[derive(Accounts)]
+pub struct WithdrawExcess<'info> {
+    #[account(mut, has_one = owner)]
+    pub group: Account<'info, GroupAccount>,
+    #[account(mut, has_one = group)]
+    pub escrow: Account<'info, EscrowAccount>,
+    #[account(address = escrow.mint @ CustomError::WrongMint)]
+    pub mint: Account<'info, Mint>,
+    #[account(mut, address = escrow.vault @ CustomError::WrongVault)]
+    pub escrow_token_account: Account<'info, TokenAccount>, // Escrow's token vault
+    #[account(mut, token::mint = mint, token::authority = owner)]
+    pub owner_token_account: Account<'info, TokenAccount>,  // Receives the excess
+    pub owner: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+}
+
+// Context for moving an escrow's tokens to or from the group's yield program; the yield
+// program's own accounts are remaining accounts
+#[derive(Accounts)]
+pub struct YieldTransfer<'info> {
+    #[account(has_one = owner)]
+    pub group: Account<'info, GroupAccount>,
+    pub owner: Signer<'info>,
+    #[account(mut, has_one = group)]
+    pub escrow: Account<'info, EscrowAccount>,
+    #[account(mut, address = escrow.vault @ CustomError::WrongVault)]
+    pub escrow_token_account: Account<'info, TokenAccount>, // Escrow's token vault
+    /// CHECK: the program id the owner configured at creation
+    #[account(executable, address = group.yield_program @ CustomError::WrongYieldProgram)]
+    pub yield_program: UncheckedAccount<'info>,
+    /// CHECK: the ProgramConfig PDA, which may not be initialized; read by load_config
+    #[account(seeds = [CONFIG_SEED], bump)]
+    pub config: UncheckedAccount<'info>,
+}
+
+// Context for a member bringing an escrow's lent tokens back; the yield program's own
+// accounts are remaining accounts
+#[derive(Accounts)]
+pub struct RecallFromYield<'info> {
+    pub group: Account<'info, GroupAccount>,
+    #[account(
+        has_one = group,
+        constraint = member.member == member_authority.key() @ CustomError::Unauthorized
+    )]
+    pub member: Account<'info, MemberAccount>,
+    pub member_authority: Signer<'info>,
+    #[account(mut, has_one = group)]
+    pub escrow: Account<'info, EscrowAccount>,
+    #[account(mut, address = escrow.vault @ CustomError::WrongVault)]
+    pub escrow_token_account: Account<'info, TokenAccount>, // Escrow's token vault
+    /// CHECK: the program id the owner configured at creation
+    #[account(executable, address = group.yield_program @ CustomError::WrongYieldProgram)]
+    pub yield_program: UncheckedAccount<'info>,
+}
+
+// Context for cancelling a group
+#[derive(Accounts)]
+pub struct CancelGroup<'info> {
+    #[account(mut, has_one = owner)]
+    pub group: Account<'info, GroupAccount>,
+    pub owner: Signer<'info>,
+}
+
+// Context for a member setting their auto-pay opt-in
+#[derive(Accounts)]
+pub struct SetAutoPay<'info> {
+    #[account(
+        mut,
+        constraint = member.member == member_authority.key() @ CustomError::Unauthorized
+    )]
+    pub member: Account<'info, MemberAccount>,
+    pub member_authority: Signer<'info>,
+}
+
+// Context for a member contesting the payout
+#[derive(Accounts)]
+pub struct RaiseDispute<'info> {
+    #[account(mut)]
+    pub group: Account<'info, GroupAccount>,
+    #[account(
+        has_one = group,
+        constraint = member.member == member_authority.key() @ CustomError::Unauthorized
+    )]
+    pub member: Account<'info, MemberAccount>,
+    pub member_authority: Signer<'info>,
+}
+
+// Context for the owner settling a dispute
+#[derive(Accounts)]
+pub struct ResolveDispute<'info> {
+    #[account(mut, has_one = owner)]
+    pub group: Account<'info, GroupAccount>,
+    pub owner: Signer<'info>,
+}
+
+// Context for pinning the token account an escrow pays out to
+#[derive(Accounts)]
+#[instruction(new_account: Pubkey)]
+pub struct SetPayoutAccount<'info> {
+    #[account(has_one = owner)]
+    pub group: Account<'info, GroupAccount>,
+    #[account(mut, has_one = group)]
+    pub escrow: Account<'info, EscrowAccount>,
+    #[account(address = new_account)]
+    pub new_payout_account: Account<'info, TokenAccount>,
+    pub owner: Signer<'info>,
+}
 
-        // Ensure group is still active and user hasn't paid yet
-        require!(group.is_active, CustomError::InactiveGroup);
-        require!(!member.has_paid, CustomError::AlreadyPaid);
+// Context for pausing or resuming deposits
+#[derive(Accounts)]
+pub struct SetPaused<'info> {
+    #[account(mut, has_one = owner)]
+    pub group: Account<'info, GroupAccount>,
+    pub owner: Signer<'info>,
+}
 
-        // Transfer SPL tokens from member to escrow
-        let cpi_accounts = Transfer {
-            from: ctx.accounts.from_token_account.to_account_info(),
-            to: ctx.accounts.escrow_token_account.to_account_info(),
-            authority: ctx.accounts.member_authority.to_account_info(),
-        };
-        let cpi_ctx = CpiContext::new(ctx.accounts.token_program.to_account_info(), cpi_accounts);
-        token::transfer(cpi_ctx, amount)?;
+// Context for nominating a new group owner
+#[derive(Accounts)]
+pub struct TransferOwnership<'info> {
+    #[account(mut, has_one = owner)]
+    pub group: Account<'info, GroupAccount>,
+    pub owner: Signer<'info>,
+}
 
-        // Record contribution in member account
-        member.contributed = amount;
-        member.has_paid = true;
-        escrow.total_held += amount;
+// Context for accepting ownership of a group
+#[derive(Accounts)]
+pub struct AcceptOwnership<'info> {
+    #[account(
+        mut,
+        constraint = group.pending_owner == Some(new_owner.key()) @ CustomError::NotPendingOwner
+    )]
+    pub group: Account<'info, GroupAccount>,
+    pub new_owner: Signer<'info>,
+}
 
-        Ok(())
-    }
+// Context for closing a finished group
+#[derive(Accounts)]
+pub struct CloseGroup<'info> {
+    #[account(mut, has_one = owner, close = owner)]
+    pub group: Account<'info, GroupAccount>,
+    #[account(mut, has_one = group, close = owner)]
+    pub escrow: Account<'info, EscrowAccount>,
+    #[account(mut, address = escrow.vault @ CustomError::WrongVault)]
+    pub vault: Option<Account<'info, TokenAccount>>, // The escrow's token account; None for native groups
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+}
 
-    // Executes payout to the group owner after subscription due time
-    pub fn execute_payout(ctx: Context<ExecutePayout>) -> Result<()> {
-        let group = &mut ctx.accounts.group;
-        let escrow = &mut ctx.accounts.escrow;
+// Context for cleaning up an abandoned group; any wallet may sign
+#[derive(Accounts)]
+pub struct SweepEmptyGroup<'info> {
+    #[account(mut, has_one = owner, close = owner)]
+    pub group: Account<'info, GroupAccount>,
+    #[account(mut, has_one = group, close = owner)]
+    pub escrow: Account<'info, EscrowAccount>,
+    #[account(mut, address = escrow.vault @ CustomError::WrongVault)]
+    pub vault: Option<Account<'info, TokenAccount>>, // The escrow's token account; None for native groups
+    #[account(mut)]
+    pub owner: SystemAccount<'info>, // Receives the rent
+    pub caller: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+}
 
-        // Ensure current time is past the subscription due time
-        require!(Clock::get()?.unix_timestamp >= group.subscription_due, CustomError::TooEarly);
+// Context for renaming a group; `realloc` moves the rent difference to or from the owner
+#[derive(Accounts)]
+#[instruction(new_name: String)]
+pub struct UpdateName<'info> {
+    #[account(
+        mut,
+        has_one = owner,
+        realloc = GroupAccount::space_for(&new_name),
+        realloc::payer = owner,
+        realloc::zero = false
+    )]
+    pub group: Account<'info, GroupAccount>,
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
 
-        let amount = escrow.total_held;
+// Context for moving a group's due time
+#[derive(Accounts)]
+pub struct ExtendDue<'info> {
+    #[account(mut, has_one = owner)]
+    pub group: Account<'info, GroupAccount>,
+    pub owner: Signer<'info>,
+}
 
-        // Use escrow account as signer via PDA
-        let group_key = group.key();
-        let seeds: &[&[u8]] = &[group_key.as_ref(), &[escrow.bump]];
-        let signer = &[seeds];
-
-        // Transfer SPL tokens from escrow to owner's token account
-        let cpi_accounts = Transfer {
-            from: ctx.accounts.escrow_token_account.to_account_info(),
-            to: ctx.accounts.owner_token_account.to_account_info(),
-            authority: ctx.accounts.escrow.to_account_info(),
-        };
-        let cpi_ctx = CpiContext::new_with_signer(ctx.accounts.token_program.to_account_info(), cpi_accounts, signer);
-        token::transfer(cpi_ctx, amount)?;
+// Context for changing a group's total cost
+#[derive(Accounts)]
+pub struct UpdateCost<'info> {
+    #[account(mut, has_one = owner)]
+    pub group: Account<'info, GroupAccount>,
+    #[account(has_one = group)]
+    pub escrow: Account<'info, EscrowAccount>,
+    /// CHECK: the ProgramConfig PDA, which may not be initialized; read by load_config
+    #[account(seeds = [CONFIG_SEED], bump)]
+    pub config: UncheckedAccount<'info>,
+    pub owner: Signer<'info>,
+}
 
-        group.is_active = false; // Mark group as completed/inactive
-        Ok(())
-    }
+// Context for adding a one-time charge; member accounts are passed as remaining accounts
+#[derive(Accounts)]
+pub struct AddSurcharge<'info> {
+    #[account(mut, has_one = owner)]
+    pub group: Account<'info, GroupAccount>,
+    /// CHECK: the ProgramConfig PDA, which may not be initialized; read by load_config
+    #[account(seeds = [CONFIG_SEED], bump)]
+    pub config: UncheckedAccount<'info>,
+    pub owner: Signer<'info>,
 }
 
-// Context for creating a group
+// Context for starting a new billing cycle; member accounts are passed as remaining accounts
 #[derive(Accounts)]
-#[instruction(group_name: String)]
-pub struct CreateGroup<'info> {
-    #[account(init, payer = owner, space = 8 + 128)]
+pub struct RenewGroup<'info> {
+    #[account(mut, has_one = owner)]
     pub group: Account<'info, GroupAccount>,
-    #[account(mut)]
     pub owner: Signer<'info>,
-    pub system_program: Program<'info, System>,
 }
 
-// Context for inviting a member
+// Context for closing an extra escrow of a finished group
 #[derive(Accounts)]
-pub struct InviteMember<'info> {
-    #[account(mut)]
+pub struct CloseEscrow<'info> {
+    #[account(mut, has_one = owner)]
     pub group: Account<'info, GroupAccount>,
-    #[account(init, payer = member_authority, space = 8 + 64)]
-    pub member: Account<'info, MemberAccount>,
+    #[account(mut, has_one = group, close = owner)]
+    pub escrow: Account<'info, EscrowAccount>,
+    #[account(mut, address = escrow.vault @ CustomError::WrongVault)]
+    pub vault: Option<Account<'info, TokenAccount>>, // The escrow's token account; None for native groups
     #[account(mut)]
-    pub member_authority: Signer<'info>,
-    pub system_program: Program<'info, System>,
+    pub owner: Signer<'info>,
+    pub token_program: Program<'info, Token>,
 }
 
-// Context for depositing funds into the escrow
+// Context for reading a member's outstanding balance
 #[derive(Accounts)]
-pub struct DepositFunds<'info> {
-    #[account(mut)]
+pub struct GetOutstanding<'info> {
     pub group: Account<'info, GroupAccount>,
-    #[account(mut)]
+    #[account(has_one = group)]
     pub member: Account<'info, MemberAccount>,
-    #[account(mut)]
-    pub member_authority: Signer<'info>,
-    #[account(mut)]
-    pub from_token_account: Account<'info, TokenAccount>, // Member's token account
-    #[account(mut)]
-    pub escrow_token_account: Account<'info, TokenAccount>, // Escrow's token account
-    #[account(mut)]
+}
+
+// Context for reading how long a group's payout is still locked
+#[derive(Accounts)]
+pub struct SecondsUntilPayout<'info> {
+    pub group: Account<'info, GroupAccount>,
+}
+
+// Context for reading a group's status snapshot
+#[derive(Accounts)]
+pub struct GetGroupStatus<'info> {
+    pub group: Account<'info, GroupAccount>,
+    #[account(has_one = group)]
     pub escrow: Account<'info, EscrowAccount>,
-    pub token_program: Program<'info, Token>,
 }
 
-// Context for executing payout to group owner
+// Context for comparing an escrow's books with its vault
 #[derive(Accounts)]
-pub struct ExecutePayout<'info> {
-    #[account(mut)]
+pub struct CheckSolvency<'info> {
     pub group: Account<'info, GroupAccount>,
-    #[account(mut)]
+    #[account(has_one = group)]
     pub escrow: Account<'info, EscrowAccount>,
-    #[account(mut)]
-    pub escrow_token_account: Account<'info, TokenAccount>, // Escrow's token account
-    #[account(mut)]
-    pub owner_token_account: Account<'info, TokenAccount>,  // Group owner's token account
-    pub token_program: Program<'info, Token>,
+    #[account(address = escrow.vault @ CustomError::WrongVault)]
+    pub escrow_token_account: Account<'info, TokenAccount>, // Escrow's token vault
+}
+
+// Collection progress returned by get_group_status
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct GroupStatus {
+    pub member_count: u8,
+    pub paid_count: u8,
+    pub total_held: u64,
+    pub is_active: bool,
+    pub seconds_until_due: i64, // Zero once the due time has passed
+}
+
+// Hands out the nonces of one owner's groups, in order
+#[account]
+pub struct OwnerCounter {
+    pub owner: Pubkey,
+    pub next_nonce: u64, // Nonce the owner's next group must be created with
+    pub bump: u8,
+}
+
+impl OwnerCounter {
+    pub const SPACE: usize = 8 // Discriminator
+        + 32                   // owner
+        + 8                    // next_nonce
+        + 1;                   // bump
+}
+
+// Program-wide defaults and caps; a single PDA at [CONFIG_SEED]
+#[account]
+#[derive(Default)]
+pub struct ProgramConfig {
+    pub admin: Pubkey,               // Only key that can update the config
+    pub default_fee_bps: u16,        // Fee of groups created without one
+    pub max_total_cost: u64,         // Cap on a group's total cost; 0 for MAX_TOTAL_COST
+    pub anti_self_split: bool,       // New groups need two members, and two paying members to pay out
+    pub paused: bool,                // Circuit breaker for deposits, invites and new groups
+    pub bump: u8,
+    pub yield_programs: Vec<Pubkey>, // Lending programs groups may lend through, at most MAX_YIELD_PROGRAMS
+}
+
+impl ProgramConfig {
+    pub const SPACE: usize = 8 // Discriminator
+        + 32                   // admin
+        + 2                    // default_fee_bps
+        + 8                    // max_total_cost
+        + 1                    // anti_self_split
+        + 1                    // paused
+        + 1                    // bump
+        + (4 + 32 * MAX_YIELD_PROGRAMS); // yield_programs
+
+    // Highest total cost a group may be created with or updated to
+    pub fn cost_cap(&self) -> u64 {
+        if self.max_total_cost == 0 {
+            MAX_TOTAL_COST
+        } else {
+            self.max_total_cost
+        }
+    }
 }
 
 // Group metadata and configuration
 #[account]
 pub struct GroupAccount {
     pub owner: Pubkey,
+    pub mint: Pubkey,
     pub group_name: String,
     pub total_cost: u64,
     pub subscription_due: i64,
+    pub grace_period: i64,
     pub member_count: u8,
+    pub total_weight: u16,   // Sum of all member weights
+    pub paid_count: u8,     // Members whose share is fully covered
+    pub allow_partial: bool, // Payout doesn't wait for every member to pay
     pub is_active: bool,
+    pub is_cancelled: bool,
+    pub is_native: bool,
+    pub pending_owner: Option<Pubkey>, // Nominated owner awaiting acceptance
+    pub is_paused: bool,               // Deposits are temporarily halted
+    pub fee_bps: u16,                  // Platform fee taken at payout, in basis points
+    pub fee_recipient: Pubkey,         // Wallet receiving the platform fee
+    pub cycle_count: u32,              // Billing cycles paid out so far
+    pub min_members: u8,               // Members required before payout
+    pub max_members: u8,               // Seats available in the group
+    pub recipients: Vec<Recipient>,    // Co-owners splitting the payout; empty pays the owner
+    pub members: Vec<Pubkey>,          // Wallets of invited members, at most MAX_LISTED_MEMBERS
+    pub disputed: bool,                // A member has frozen the payout
+    pub paid_out_at: i64,              // Time of the latest payout, zero if never paid out
+    pub escrow_count: u8,              // Escrows created, one per accepted mint
+    pub escrows_paid: u8,              // Escrows paid out in the current cycle
+    pub payout_recipient: Pubkey,      // Receives payouts when there are no co-recipients
+    pub late_fee_bps: u16,             // Surcharge on deposits after the due time, in basis points
+    pub bump: u8,                      // Canonical bump of the group PDA, for signing as the group
+    pub invite_only: bool,             // invite_member needs the owner's signature
+    pub max_per_member: u64,           // Cap on a member's contribution per cycle; 0 for no cap
+    pub allow_late_join: bool,         // Members may join after the first deposit
+    pub total_held: u64,               // Deposits held across every escrow of the group
+    pub stream_payouts: bool,          // Deposits are forwarded to the payout recipient on arrival
+    pub streamed_total: u64,           // Deposits forwarded so far, across every cycle
+    pub min_deposit: u64,              // Smallest deposit accepted, unless it completes the share
+    pub category: u8,                  // A GroupCategory discriminant, for off-chain discovery
+    pub yield_enabled: bool,           // Escrowed tokens may be lent out through yield_program
+    pub yield_program: Pubkey,         // Lending program the escrows deposit into
+    pub allowed_mints: Vec<Pubkey>,    // Mints deposits may be made in; empty for any
+    pub allow_credit: bool,            // Deposits past the share become credit instead of failing
+    pub restrict_payout: bool,         // Payouts need the owner's signature instead of anyone's
+    pub symbol: [u8; SYMBOL_LEN],      // Presentation only; never read by the program
+    pub decimals_hint: u8,             // Presentation only; the mint's decimals still govern amounts
+    pub approvers: Vec<Pubkey>,        // Wallets whose approvals count toward large payouts
+    pub threshold: u8,                 // Approvals a large payout needs
+    pub approval_above: u64,           // Escrow balance above which payouts need approvals
+    pub settlement_delay: i64,         // Extra seconds after grace_end() before payout opens
+    pub anti_self_split: bool,         // Payout needs two paying members, from ProgramConfig
+    pub nonce: u64,                    // Part of the group's seeds, unique per owner
+    pub refund_penalty_bps: u16,       // Kept from early refunds and paid out with the escrow
+    pub deposit_deadline: i64,         // Deposits close after it, ahead of subscription_due; 0 for none
+    pub reject_empty_payout: bool,     // Payouts fail with NothingToPayOut when nothing was collected
+    pub surcharge: u64,                // Part of total_cost added by add_surcharge for this cycle only
+    pub fees_held: u64,                // Part of total_held paid as late surcharges or refund penalties
+    pub payout_in_progress: bool,      // An escrow is part-way through execute_payout_page
+    pub pinned_cost: u64,              // Sum of the shares pinned on member accounts this cycle
+    pub pinned_weight: u16,            // Weight of the members with a pinned share
+    pub pinned_count: u8,              // Members with a pinned share
+}
+
+impl GroupAccount {
+    pub const SPACE: usize = 8 // Discriminator
+        + 32                   // owner
+        + 32                   // mint
+        + (4 + MAX_NAME_LEN)   // group_name
+        + 8                    // total_cost
+        + 8                    // subscription_due
+        + 8                    // grace_period
+        + 1                    // member_count
+        + 2                    // total_weight
+        + 1                    // paid_count
+        + 1                    // allow_partial
+        + 1                    // is_active
+        + 1                    // is_cancelled
+        + 1                    // is_native
+        + (1 + 32)             // pending_owner
+        + 1                    // is_paused
+        + 2                    // fee_bps
+        + 32                   // fee_recipient
+        + 4                    // cycle_count
+        + 1                    // min_members
+        + 1                    // max_members
+        + (4 + MAX_RECIPIENTS * Recipient::SPACE) // recipients
+        + (4 + MAX_LISTED_MEMBERS * 32)           // members
+        + 1                                       // disputed
+        + 8                                       // paid_out_at
+        + 1                                       // escrow_count
+        + 1                                       // escrows_paid
+        + 32                                      // payout_recipient
+        + 2                                       // late_fee_bps
+        + 1                                       // bump
+        + 1                                       // invite_only
+        + 8                                       // max_per_member
+        + 1                                       // allow_late_join
+        + 8                                       // total_held
+        + 1                                       // stream_payouts
+        + 8                                       // streamed_total
+        + 8                                       // min_deposit
+        + 1                                       // category
+        + 1                                       // yield_enabled
+        + 32                                      // yield_program
+        + (4 + 32 * MAX_ALLOWED_MINTS)            // allowed_mints
+        + 1                                       // allow_credit
+        + 1                                       // restrict_payout
+        + SYMBOL_LEN                              // symbol
+        + 1                                       // decimals_hint
+        + (4 + 32 * MAX_APPROVERS)                // approvers
+        + 1                                       // threshold
+        + 8                                       // approval_above
+        + 8                                       // settlement_delay
+        + 1                                       // anti_self_split
+        + 8                                       // nonce
+        + 2                                       // refund_penalty_bps
+        + 8                                       // deposit_deadline
+        + 1                                       // reject_empty_payout
+        + 8                                       // surcharge
+        + 8                                       // fees_held
+        + 1                                       // payout_in_progress
+        + 8                                       // pinned_cost
+        + 2                                       // pinned_weight
+        + 1;                                      // pinned_count
+
+    // Size of a group named `name` once trimmed; SPACE leaves room for the longest name
+    pub fn space_for(name: &str) -> usize {
+        Self::SPACE - MAX_NAME_LEN + name.trim().len()
+    }
+
+    // Share owed by a member. Each unit of weight costs `total_cost / total_weight`
+    // rounded down. The dust `total_cost % total_weight` is spread one base unit at a
+    // time over the members in invite order: with `n` members, each owes `dust / n`
+    // extra and the first `dust % n` owe one more, so shares always sum to `total_cost`.
+    // For example 100 split three ways is 34/33/33.
+    // Once shares are pinned, the members without one split what the pinned shares leave
+    // of the cost the same way, except that the dust is left over for whoever pins last,
+    // whose share is then the rest of the cost. Pinned and unpinned shares always add up
+    // to `total_cost`.
+    pub fn share_of(&self, member: &MemberAccount) -> Result<u64> {
+        require!(self.total_weight > 0, CustomError::NoMembers);
+        if self.pinned_count > 0 {
+            let cost = self.total_cost.checked_sub(self.pinned_cost).ok_or(CustomError::Overflow)?;
+            let count = self.member_count.checked_sub(self.pinned_count).ok_or(CustomError::Overflow)?;
+            let weight = self.total_weight.checked_sub(self.pinned_weight).ok_or(CustomError::Overflow)?;
+            return Ok(required_deposit(cost, count, member.weight, weight, None));
+        }
+        let index = self.members.iter().position(|m| *m == member.member);
+        Ok(required_deposit(self.total_cost, self.member_count, member.weight, self.total_weight, index))
+    }
+
+    // Share a member is held to: the one pinned on their account, or while nothing is
+    // pinned yet, their current share_of
+    pub fn owed_by(&self, member: &MemberAccount) -> Result<u64> {
+        match member.owed {
+            0 => self.share_of(member),
+            owed => Ok(owed),
+        }
+    }
+
+    // Pins the member's share at their current share_of, unless it is pinned already
+    pub fn pin(&mut self, member: &mut MemberAccount) -> Result<()> {
+        if member.owed > 0 {
+            return Ok(());
+        }
+        member.owed = self.share_of(member)?;
+        self.pinned_cost = self.pinned_cost.checked_add(member.owed).ok_or(CustomError::Overflow)?;
+        self.pinned_weight = self.pinned_weight.checked_add(member.weight).ok_or(CustomError::Overflow)?;
+        self.pinned_count = self.pinned_count.checked_add(1).ok_or(CustomError::Overflow)?;
+        Ok(())
+    }
+
+    // Invites happen before anyone deposits, while the split is still taking shape, so
+    // shares are normally pinned by the member's first deposit. A member joining after
+    // deposits arrived has their share pinned right away, from what is left of the cost;
+    // members who already deposited keep theirs.
+    pub fn pin_late_join(&mut self, member: &mut MemberAccount) -> Result<()> {
+        if self.total_held > 0 {
+            self.pin(member)?;
+        }
+        Ok(())
+    }
+
+    // Releases a member's pinned share back into the rest of the cost, e.g. on a refund
+    pub fn unpin(&mut self, member: &mut MemberAccount) -> Result<()> {
+        if member.owed == 0 {
+            return Ok(());
+        }
+        self.pinned_cost = self.pinned_cost.checked_sub(member.owed).ok_or(CustomError::Overflow)?;
+        self.pinned_weight = self.pinned_weight.checked_sub(member.weight).ok_or(CustomError::Overflow)?;
+        self.pinned_count = self.pinned_count.checked_sub(1).ok_or(CustomError::Overflow)?;
+        member.owed = 0;
+        Ok(())
+    }
+
+    // Amount a member still owes toward their share, never negative
+    pub fn outstanding_of(&self, member: &MemberAccount) -> Result<u64> {
+        Ok(self.owed_by(member)?.saturating_sub(member.contributed))
+    }
+
+    // Platform fee owed on a payout of `amount`, rounded down
+    pub fn fee_for(&self, amount: u64) -> u64 {
+        (u128::from(amount) * u128::from(self.fee_bps) / u128::from(BPS_DENOMINATOR)) as u64
+    }
+
+    // Surcharge owed on a late payment of `amount`, rounded down
+    pub fn late_fee_for(&self, amount: u64) -> u64 {
+        (u128::from(amount) * u128::from(self.late_fee_bps) / u128::from(BPS_DENOMINATOR)) as u64
+    }
+
+    // Penalty kept from an early refund of `amount`, rounded down
+    pub fn refund_penalty_for(&self, amount: u64) -> u64 {
+        (u128::from(amount) * u128::from(self.refund_penalty_bps) / u128::from(BPS_DENOMINATOR)) as u64
+    }
+
+    // Splits a payout of `total` into the platform fee and the remainder, dividing the
+    // remainder between co-recipients by their bps if there are any. Rounding dust goes
+    // to the last recipient so the shares always add up to the remainder.
+    pub fn split_payout(&self, total: u64) -> Result<PayoutSplit> {
+        let fee = self.fee_for(total);
+        let amount = total.checked_sub(fee).ok_or(CustomError::Overflow)?;
+
+        let mut shares = Vec::with_capacity(self.recipients.len());
+        let mut left = amount;
+        for (i, recipient) in self.recipients.iter().enumerate() {
+            let share = if i + 1 == self.recipients.len() {
+                left
+            } else {
+                (u128::from(amount) * u128::from(recipient.bps) / u128::from(BPS_DENOMINATOR)) as u64
+            };
+            left = left.checked_sub(share).ok_or(CustomError::Overflow)?;
+            shares.push((recipient.pubkey, share));
+        }
+        Ok(PayoutSplit { fee, amount, shares })
+    }
+
+    // Large payouts need `threshold` of the approvers to have signed off on the escrow
+    pub fn check_approvals(&self, escrow: &EscrowAccount) -> Result<()> {
+        if escrow.total_held > self.approval_above {
+            require!(escrow.approvals.count_ones() >= u32::from(self.threshold), CustomError::ApprovalsMissing);
+        }
+        Ok(())
+    }
+
+    // Last moment deposits are accepted
+    pub fn grace_end(&self) -> i64 {
+        self.subscription_due + self.grace_period
+    }
+
+    // First moment the group can be paid out, a settlement delay after the grace period
+    pub fn payout_opens_at(&self) -> i64 {
+        self.grace_end().saturating_add(self.settlement_delay)
+    }
+
+    // Stops collection for good so members can refund
+    pub fn cancel(&mut self) {
+        self.is_active = false;
+        self.is_cancelled = true;
+        self.members.clear();
+    }
+
+    // Counts and lists a newly invited member, failing once the group or the list is full
+    pub fn add_member(&mut self, member: Pubkey, weight: u16) -> Result<()> {
+        require!(self.member_count < self.max_members, CustomError::GroupFull);
+        require!(self.members.len() < MAX_LISTED_MEMBERS, CustomError::MemberListFull);
+        self.member_count = self.member_count.checked_add(1).ok_or(CustomError::Overflow)?;
+        self.total_weight = self.total_weight.checked_add(weight).ok_or(CustomError::Overflow)?;
+        self.check_shares()?;
+        self.members.push(member);
+        Ok(())
+    }
+
+    // Ensures every member owes something: a member with a share of zero could never
+    // deposit toward it, so would never count as paid. Leaving at least the unpinned
+    // members' weight of the cost gives each of them at least one unit per unit of weight.
+    pub fn check_shares(&self) -> Result<()> {
+        let cost = self.total_cost.checked_sub(self.pinned_cost).ok_or(CustomError::Overflow)?;
+        let weight = self.total_weight.checked_sub(self.pinned_weight).ok_or(CustomError::Overflow)?;
+        require!(cost >= u64::from(weight), CustomError::ShareTooSmall);
+        Ok(())
+    }
+
+    // Whether the group takes deposits in `mint`
+    pub fn accepts_mint(&self, mint: &Pubkey) -> bool {
+        self.allowed_mints.is_empty() || self.allowed_mints.contains(mint)
+    }
+
+    // Uncounts and unlists a member when they are removed from the group. Their weight
+    // leaves total_weight with them so the remaining shares are recomputed from it.
+    pub fn remove_member(&mut self, member: Pubkey, weight: u16) -> Result<()> {
+        self.member_count = self.member_count.checked_sub(1).ok_or(CustomError::Overflow)?;
+        self.total_weight = self.total_weight.checked_sub(weight).ok_or(CustomError::WeightMismatch)?;
+        // Every remaining member still carries a weight of at least one
+        require!(
+            self.total_weight >= u16::from(self.member_count) && (self.member_count > 0 || self.total_weight == 0),
+            CustomError::WeightMismatch
+        );
+        self.members.retain(|m| *m != member);
+        Ok(())
+    }
 }
 
-// Individual member contributions
+// Individual member contributions. The fields up to has_paid are filtered on by
+// unpaid_filter; keep them in place.
 #[account]
 pub struct MemberAccount {
     pub group: Pubkey,
     pub member: Pubkey,
     pub contributed: u64,
     pub has_paid: bool,
+    pub paid_late: bool,                 // Deposited after the deadline, within the grace period
+    pub weight: u16,                     // Seats paid for, relative to the group's total_weight
+    pub deposit_mint: Pubkey,            // Mint of the current contribution
+    pub total_contributed_lifetime: u64, // Contributions kept across every cycle
+    pub cycles_paid: u32,                // Cycles in which the full share was paid
+    pub late_fee_paid: u64,              // Late surcharge paid on top of the share this cycle
+    pub member_due: i64,                 // Personal deposit deadline; never earlier than the group's
+    pub owed: u64,                       // Share pinned this cycle; 0 until pinned
+    pub credit: u64,                     // Overpayment held toward the next cycle's share
+    pub auto_pay: bool,                  // Member's opt-in to auto-paying the next cycle
+    pub first_deposit_at: i64,           // Time of the first deposit this cycle; 0 before it
+}
+
+impl MemberAccount {
+    // A fresh, unpaid membership
+    pub fn new(group: Pubkey, member: Pubkey, weight: u16, member_due: i64) -> Self {
+        Self {
+            group,
+            member,
+            contributed: 0,
+            has_paid: false, // Mark as not paid
+            paid_late: false,
+            weight,          // Seats this member pays for
+            deposit_mint: Pubkey::default(),
+            total_contributed_lifetime: 0,
+            cycles_paid: 0,
+            late_fee_paid: 0,
+            member_due,
+            owed: 0,
+            credit: 0,
+            auto_pay: false,
+            first_deposit_at: 0,
+        }
+    }
+
+    // Whether the escrow holds anything for this member, toward the share or as credit
+    pub fn has_funds(&self) -> bool {
+        self.contributed > 0 || self.credit > 0
+    }
+
+    // The member's deposit deadline; an extended or renewed group due time applies to
+    // everyone whose own deadline it passes
+    pub fn due(&self, group: &GroupAccount) -> i64 {
+        self.member_due.max(group.subscription_due)
+    }
+
+    pub const SPACE: usize = 8 // Discriminator
+        + 32                   // group
+        + 32                   // member
+        + 8                    // contributed
+        + 1                    // has_paid
+        + 1                    // paid_late
+        + 2                    // weight
+        + 32                   // deposit_mint
+        + 8                    // total_contributed_lifetime
+        + 4                    // cycles_paid
+        + 8                    // late_fee_paid
+        + 8                    // member_due
+        + 8                    // owed
+        + 8                    // credit
+        + 1                    // auto_pay
+        + 8;                   // first_deposit_at
 }
 
 // Escrow account that holds SPL tokens until payout
@@ -170,7 +3311,161 @@ pub struct MemberAccount {
 pub struct EscrowAccount {
     pub group: Pubkey,
     pub total_held: u64,
-    pub bump: u8, // PDA bump seed
+    pub bump: u8,               // PDA bump seed
+    pub vault: Pubkey,          // Token vault PDA; unused by native SOL groups
+    pub mint: Pubkey,           // Mint this escrow collects; Pubkey::default() for native SOL
+    pub paid_cycles: u32,       // Billing cycles this escrow has been paid out for
+    pub late_fees: u64,         // Part of total_held paid as late surcharges
+    pub payout_account: Pubkey, // Pinned payout token account, or default for any of the recipient's
+    pub paid_cursor: u8,        // Co-recipients paid so far by execute_payout_page
+    pub in_yield: u64,          // Tokens lent to the group's yield program
+    pub credit_held: u64,       // Members' credit, held apart from total_held
+    pub approvals: u8,          // Bit i set once group.approvers[i] approved this payout
+    pub penalties: u64,         // Part of total_held kept from early refunds
+}
+
+impl EscrowAccount {
+    pub const SPACE: usize = 8 // Discriminator
+        + 32                   // group
+        + 8                    // total_held
+        + 1                    // bump
+        + 32                   // vault
+        + 32                   // mint
+        + 4                    // paid_cycles
+        + 8                    // late_fees
+        + 32                   // payout_account
+        + 1                    // paid_cursor
+        + 8                    // in_yield
+        + 8                    // credit_held
+        + 1                    // approvals
+        + 8;                   // penalties
+
+    // Seeds for signing as the escrow PDA
+    pub fn signer_seeds(&self) -> [&[u8]; 3] {
+        [self.group.as_ref(), self.mint.as_ref(), std::slice::from_ref(&self.bump)]
+    }
+
+    // Whether payouts may go to `account`: the pinned payout account if one was set,
+    // otherwise any account of the payout recipient
+    pub fn pays_to(&self, account: &Pubkey) -> bool {
+        self.payout_account == Pubkey::default() || self.payout_account == *account
+    }
+
+    // Checks that the stored seeds and bump derive `address`, so signing with them signs
+    // for this very escrow. The escrow was created at the canonical bump, so any other
+    // bump derives a different address or none at all.
+    pub fn verify_address(&self, address: &Pubkey) -> Result<()> {
+        let derived = Pubkey::create_program_address(&self.signer_seeds(), &crate::ID)
+            .map_err(|_| error!(CustomError::InvalidBump))?;
+        require_keys_eq!(derived, *address, CustomError::InvalidBump);
+        Ok(())
+    }
+
+    // Whether the escrow holds nothing for anyone, in deposits or credit
+    pub fn is_empty(&self) -> bool {
+        self.total_held == 0 && self.credit_held == 0
+    }
+
+    // Tokens the vault should hold by the books: deposits and credit, less what is lent to
+    // the yield program. Streamed deposits leave the vault as they arrive.
+    pub fn expected_vault_balance(&self, group: &GroupAccount) -> u64 {
+        if group.stream_payouts {
+            return 0;
+        }
+        self.total_held.saturating_add(self.credit_held).saturating_sub(self.in_yield)
+    }
+
+    // Checks the vault really holds what the escrow has recorded, so a payout never
+    // fails half-way or pays out less than the books say
+    pub fn check_funded(&self, vault_balance: u64) -> Result<()> {
+        require!(self.in_yield == 0, CustomError::YieldOutstanding);
+        let recorded = self.total_held.checked_add(self.credit_held).ok_or(CustomError::Overflow)?;
+        require!(vault_balance >= recorded, CustomError::VaultUnderfunded);
+        Ok(())
+    }
+}
+
+// Emitted when a group is created
+#[event]
+pub struct GroupCreated {
+    pub group: Pubkey,
+    pub owner: Pubkey,
+    pub mint: Pubkey,
+    pub total_cost: u64,
+    pub subscription_due: i64,
+    pub category: u8, // A GroupCategory discriminant
+    pub symbol: [u8; SYMBOL_LEN],
+    pub decimals_hint: u8,
+    pub timestamp: i64,
+}
+
+// Emitted when a member's deposit lands in escrow
+#[event]
+pub struct MemberDeposited {
+    pub group: Pubkey,
+    pub member: Pubkey,
+    pub amount: u64,
+    pub total_held: u64, // Escrow balance after the deposit
+    pub memo: [u8; 32],  // Member's reference from deposit_funds_with_memo; zeros without one
+    pub timestamp: i64,
+}
+
+// Emitted by check_solvency
+#[event]
+pub struct SolvencyChecked {
+    pub group: Pubkey,
+    pub escrow: Pubkey,
+    pub expected: u64, // Vault balance by the escrow's books
+    pub actual: u64,   // Vault balance on chain
+    pub delta: i64,    // actual - expected, saturating
+}
+
+// Emitted when escrowed funds are paid out
+#[event]
+pub struct PayoutExecuted {
+    pub group: Pubkey,
+    pub recipient: Pubkey, // Token account that received the funds
+    pub amount: u64, // Paid to the recipient, after the fee
+    pub fee: u64,    // Paid to the platform fee recipient
+    pub timestamp: i64,
+}
+
+// Emitted when the owner sweeps an underfunded escrow
+#[event]
+pub struct EmergencyWithdrawn {
+    pub group: Pubkey,
+    pub recipient: Pubkey, // Account that received the funds
+    pub amount: u64, // Paid to the recipient, after the fee
+    pub fee: u64,    // Paid to the platform fee recipient
+    pub timestamp: i64,
+}
+
+// Emitted by add_surcharge so clients can prompt members to top up
+#[event]
+pub struct SurchargeAdded {
+    pub group: Pubkey,
+    pub extra: u64,      // Added to the current cycle's cost
+    pub total_cost: u64, // Cost including the surcharge
+    pub timestamp: i64,
+}
+
+// Emitted when a group starts a new cycle, with how each member paid in the last one
+#[event]
+pub struct RenewalSummary {
+    pub group: Pubkey,
+    pub cycle: u32, // Cycles paid out so far, including the one just ended
+    pub members: Vec<MemberReliability>,
+    pub next_due: i64,
+}
+
+// A member's payment record for one cycle, reported by RenewalSummary
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct MemberReliability {
+    pub member: Pubkey,
+    pub paid: bool,       // Covered their share in the cycle
+    pub paid_late: bool,  // Paid after the due time, within the grace period
+    pub cycles_paid: u32, // Cycles paid in full over the membership
+    pub auto_pay: bool,   // Signalled they'll auto-pay the next cycle
 }
 
 // Custom errors for better debugging and control
@@ -182,5 +3477,402 @@ pub enum CustomError {
     AlreadyPaid,
     #[msg("Payout attempted before due time.")]
     TooEarly,
+    #[msg("Arithmetic overflow.")]
+    Overflow,
+    #[msg("Refund attempted after due time.")]
+    TooLate,
+    #[msg("Member has nothing to refund.")]
+    NothingToRefund,
+    #[msg("Signer does not own this membership.")]
+    Unauthorized,
+    #[msg("Group has been cancelled.")]
+    GroupCancelled,
+    #[msg("Token mint does not match the group's mint.")]
+    WrongMint,
+    #[msg("Group name is too long.")]
+    NameTooLong,
+    #[msg("Deposit amount does not match the member's share.")]
+    WrongAmount,
+    #[msg("Deposit would exceed the member's share.")]
+    ExceedsShare,
+    #[msg("Group has no members.")]
+    NoMembers,
+    #[msg("Subscription due time is in the past.")]
+    DueInPast,
+    #[msg("Total cost must be greater than zero.")]
+    ZeroCost,
+    #[msg("Group is still active.")]
+    GroupStillActive,
+    #[msg("Escrow still holds funds.")]
+    EscrowNotEmpty,
+    #[msg("Wrong instruction for the group's asset type.")]
+    WrongAssetType,
+    #[msg("Grace period cannot be negative.")]
+    InvalidGracePeriod,
+    #[msg("Deposits are closed for this group.")]
+    DepositWindowClosed,
+    #[msg("Not every member has paid their share.")]
+    NotFullyFunded,
+    #[msg("Member weight must be greater than zero.")]
+    InvalidWeight,
+    #[msg("Signer is not the nominated owner.")]
+    NotPendingOwner,
+    #[msg("Group is paused.")]
+    GroupPaused,
+    #[msg("Fee cannot exceed 100%.")]
+    InvalidFee,
+    #[msg("Group has not been paid out yet.")]
+    NotPaidOut,
+    #[msg("Every member account of the group must be supplied exactly once.")]
+    WrongMemberList,
+    #[msg("Minimum member count cannot exceed the maximum, which must be positive.")]
+    InvalidMemberBounds,
+    #[msg("Group has no free seats.")]
+    GroupFull,
+    #[msg("Group has too few members to pay out.")]
+    TooFewMembers,
+    #[msg("New due time must be later than the current one.")]
+    DueNotLater,
+    #[msg("Due time cannot be extended that far.")]
+    DueTooFar,
+    #[msg("Too many payout recipients.")]
+    TooManyRecipients,
+    #[msg("Recipient shares must add up to 100%.")]
+    InvalidRecipientSplit,
+    #[msg("Recipient accounts must match the group's recipients, in order.")]
+    WrongRecipientAccount,
+    #[msg("Group has already been paid out.")]
+    AlreadyPaidOut,
+    #[msg("Token account is not the group's vault.")]
+    WrongVault,
+    #[msg("Group member list is full.")]
+    MemberListFull,
+    #[msg("Payout is frozen by a dispute.")]
+    Disputed,
+    #[msg("Group has no open dispute.")]
+    NotDisputed,
+    #[msg("Vault holds nothing beyond tracked deposits.")]
+    NoExcess,
+    #[msg("A member's share must be paid in a single mint.")]
+    MixedMints,
+    #[msg("Close the group's other escrows first.")]
+    EscrowsOpen,
+    #[msg("The last escrow is closed together with the group.")]
+    LastEscrow,
+    #[msg("Too many members in one batch.")]
+    BatchTooLarge,
+    #[msg("Wallet is already a member of the group.")]
+    AlreadyMember,
+    #[msg("Account does not belong to the group's payout recipient.")]
+    WrongPayoutRecipient,
+    #[msg("Deposit would take the escrow past the total cost.")]
+    ExceedsCost,
+    #[msg("Group is fully funded and can be paid out.")]
+    GroupFunded,
+    #[msg("Late deposit must cover the rest of the share plus the late fee.")]
+    LateFeeRequired,
+    #[msg("Total cost is above the maximum.")]
+    CostTooHigh,
+    #[msg("Members have already deposited toward the current cost.")]
+    DepositsMade,
+    #[msg("Group has not been cancelled.")]
+    NotCancelled,
+    #[msg("Deposit would take the member past the per-member cap.")]
+    PerMemberCapExceeded,
+    #[msg("Members can't join once deposits have been made.")]
+    MembershipLocked,
+    #[msg("Only token groups without a fee or co-recipients can stream payouts.")]
+    InvalidStreamConfig,
+    #[msg("Deposits were already streamed to the payout recipient.")]
+    FundsStreamed,
+    #[msg("Group still has members.")]
+    GroupNotEmpty,
+    #[msg("Escrow bump does not derive the escrow address.")]
+    InvalidBump,
+    #[msg("Token account is frozen.")]
+    PayoutAccountFrozen,
+    #[msg("Escrow pays out to a different token account.")]
+    WrongPayoutAccount,
+    #[msg("Deposit is below the group's minimum.")]
+    DepositTooSmall,
+    #[msg("Unknown group category.")]
+    InvalidCategory,
+    #[msg("Group has no co-recipients to page through.")]
+    NoRecipients,
+    #[msg("Payout page does not continue where the last one stopped.")]
+    PageOutOfOrder,
+    #[msg("A paged payout of this escrow is under way.")]
+    PayoutInProgress,
+    #[msg("Escrow vault holds less than the recorded deposits.")]
+    VaultUnderfunded,
+    #[msg("Yield settings are invalid.")]
+    InvalidYieldConfig,
+    #[msg("Yield is not enabled for this group.")]
+    YieldDisabled,
+    #[msg("Yield program is not the one configured for this group.")]
+    WrongYieldProgram,
+    #[msg("Yield program moved a different amount than expected.")]
+    YieldMismatch,
+    #[msg("Escrow still has tokens lent out to the yield program.")]
+    YieldOutstanding,
+    #[msg("Group's total weight does not match its members.")]
+    WeightMismatch,
+    #[msg("Too many allowed mints.")]
+    TooManyMints,
+    #[msg("Group does not accept this mint.")]
+    MintNotAllowed,
+    #[msg("Escrow belongs to another group.")]
+    WrongEscrow,
+    #[msg("Due time is out of range.")]
+    InvalidDueDate,
+    #[msg("Invalid payout approvers or threshold.")]
+    InvalidApprovers,
+    #[msg("Signer is not one of the group's approvers.")]
+    NotAnApprover,
+    #[msg("Payout needs more approvals.")]
+    ApprovalsMissing,
+    #[msg("Remaining account does not match the expected entry at its position.")]
+    AccountOrderMismatch,
+    #[msg("Settlement delay must be between zero and seven days.")]
+    InvalidSettlementDelay,
+    #[msg("Group needs at least two paying members under the anti-self-split policy.")]
+    SelfSplit,
+    #[msg("Group name must be non-empty and free of control characters.")]
+    InvalidName,
+    #[msg("Nonce is not the owner's next group nonce.")]
+    WrongNonce,
+    #[msg("Program is paused by its admin.")]
+    ProgramPaused,
+    #[msg("Refund penalty must be at most 10000 bps, and zero for native SOL groups.")]
+    InvalidRefundPenalty,
+    #[msg("Deposit deadline must be in the future and no later than the due time.")]
+    InvalidDepositDeadline,
+    #[msg("The group's deposit deadline has passed.")]
+    DepositDeadlinePassed,
+    #[msg("Nothing was collected this cycle.")]
+    NothingToPayOut,
+    #[msg("Yield program is not on the program config's allowlist.")]
+    YieldProgramNotAllowed,
+    #[msg("Amount exceeds what the escrow holds.")]
+    ExceedsHoldings,
+    #[msg("Nothing is lent to the yield program.")]
+    NothingLent,
+    #[msg("Total cost is too small to give every member a share.")]
+    ShareTooSmall,
+    #[msg("Some members' shares are pinned against the current cost.")]
+    SharesPinned,
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn serialized(member: &MemberAccount) -> Vec<u8> {
+        let mut data = Vec::new();
+        member.try_serialize(&mut data).unwrap();
+        data
+    }
+
+    fn matches_all(filters: &[MemcmpFilter], data: &[u8]) -> bool {
+        filters.iter().all(|filter| filter.matches(data))
+    }
+
+    #[test]
+    fn unpaid_filter_matches_an_unpaid_member() {
+        let group = Pubkey::new_unique();
+        let member = MemberAccount::new(group, Pubkey::new_unique(), 1, 0);
+        assert!(matches_all(&unpaid_filter(&group), &serialized(&member)));
+    }
+
+    #[test]
+    fn unpaid_filter_skips_paid_members_and_other_groups() {
+        let group = Pubkey::new_unique();
+        let mut paid = MemberAccount::new(group, Pubkey::new_unique(), 1, 0);
+        paid.contributed = 100;
+        paid.has_paid = true;
+        assert!(!matches_all(&unpaid_filter(&group), &serialized(&paid)));
+
+        let elsewhere = MemberAccount::new(Pubkey::new_unique(), Pubkey::new_unique(), 1, 0);
+        assert!(!matches_all(&unpaid_filter(&group), &serialized(&elsewhere)));
+    }
+
+    #[test]
+    fn unpaid_filter_skips_other_account_types() {
+        let escrow = escrow_with_bump(0);
+        let mut data = Vec::new();
+        escrow.try_serialize(&mut data).unwrap();
+        assert!(!matches_all(&unpaid_filter(&escrow.group), &data));
+    }
+
+    #[test]
+    fn member_pda_uses_the_invite_member_seeds() {
+        let group = Pubkey::new_unique();
+        let member = Pubkey::new_unique();
+        let (address, bump) = member_pda(&group, &member);
+
+        // The seeds and canonical bump Anchor's `init` uses in InviteMember
+        let expected = Pubkey::find_program_address(&[group.as_ref(), member.as_ref()], &ID);
+        assert_eq!((address, bump), expected);
+        let created = Pubkey::create_program_address(&[group.as_ref(), member.as_ref(), &[bump]], &ID);
+        assert_eq!(created, Ok(address));
+    }
+
+    #[test]
+    fn member_pda_is_unique_per_group_and_wallet() {
+        let (group, other_group) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let (member, other_member) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let address = member_pda(&group, &member).0;
+        assert_ne!(address, member_pda(&other_group, &member).0);
+        assert_ne!(address, member_pda(&group, &other_member).0);
+    }
+
+    fn escrow_with_bump(bump: u8) -> EscrowAccount {
+        EscrowAccount {
+            group: Pubkey::new_unique(),
+            total_held: 0,
+            bump,
+            vault: Pubkey::default(),
+            mint: Pubkey::new_unique(),
+            paid_cycles: 0,
+            late_fees: 0,
+            payout_account: Pubkey::default(),
+            paid_cursor: 0,
+            in_yield: 0,
+            credit_held: 0,
+            approvals: 0,
+            penalties: 0,
+        }
+    }
+
+    #[test]
+    fn verify_address_accepts_the_canonical_bump() {
+        let mut escrow = escrow_with_bump(0);
+        let (address, bump) = Pubkey::find_program_address(&[escrow.group.as_ref(), escrow.mint.as_ref()], &ID);
+        escrow.bump = bump;
+        assert!(escrow.verify_address(&address).is_ok());
+    }
+
+    #[test]
+    fn verify_address_rejects_a_tampered_bump() {
+        let mut escrow = escrow_with_bump(0);
+        let (address, bump) = Pubkey::find_program_address(&[escrow.group.as_ref(), escrow.mint.as_ref()], &ID);
+        // Every other bump derives another address, or fails to derive one at all
+        for tampered in (0..=u8::MAX).filter(|b| *b != bump) {
+            escrow.bump = tampered;
+            assert!(escrow.verify_address(&address).is_err());
+        }
+    }
+
+    #[test]
+    fn check_funded_accepts_a_vault_holding_the_deposits() {
+        let mut escrow = escrow_with_bump(0);
+        escrow.total_held = 500;
+        assert!(escrow.check_funded(500).is_ok());
+        // Tokens sent straight to the vault are excess, not a shortfall
+        assert!(escrow.check_funded(700).is_ok());
+    }
+
+    #[test]
+    fn check_funded_rejects_a_drained_vault() {
+        let mut escrow = escrow_with_bump(0);
+        escrow.total_held = 500;
+        assert_eq!(escrow.check_funded(499).unwrap_err(), error!(CustomError::VaultUnderfunded));
+        assert!(escrow.check_funded(0).is_err());
+    }
+
+    #[test]
+    fn required_deposit_splits_evenly() {
+        assert_eq!(required_deposit(300, 3, 1, 3, Some(0)), 100);
+        assert_eq!(required_deposit(1000, 1, 1, 1, Some(0)), 1000);
+    }
+
+    #[test]
+    fn required_deposit_hands_out_positional_dust() {
+        // 100 three ways is 34/33/33; the extra unit goes to the first member invited
+        assert_eq!(required_deposit(100, 3, 1, 3, Some(0)), 34);
+        assert_eq!(required_deposit(100, 3, 1, 3, Some(1)), 33);
+        assert_eq!(required_deposit(100, 3, 1, 3, Some(2)), 33);
+        // Without an index there is no positional unit
+        assert_eq!(required_deposit(100, 3, 1, 3, None), 33);
+        // Two units of dust between two members: one each
+        assert_eq!(required_deposit(102, 2, 1, 4, Some(0)), 26);
+        assert_eq!(required_deposit(102, 2, 1, 4, Some(1)), 26);
+        // Cheaper than one unit per member: only the dust is owed
+        assert_eq!(required_deposit(2, 3, 1, 3, Some(1)), 1);
+        assert_eq!(required_deposit(2, 3, 1, 3, Some(2)), 0);
+    }
+
+    #[test]
+    fn required_deposit_weighs_shares() {
+        assert_eq!(required_deposit(1000, 2, 3, 5, Some(0)), 600);
+        assert_eq!(required_deposit(1000, 2, 2, 5, Some(1)), 400);
+        // 10 over weights 3 and 1: 2 per unit of weight, plus 1 of the 2 dust units each
+        assert_eq!(required_deposit(10, 2, 3, 4, Some(0)), 7);
+        assert_eq!(required_deposit(10, 2, 1, 4, Some(1)), 3);
+    }
+
+    #[test]
+    fn required_deposits_add_up_to_the_cost() {
+        // Weights 1 and 3: shares of 7 are 3/4 but of 8 are 2/6, so a surcharge's parts
+        // can't come from the difference of the two
+        let weights = [1u16, 3];
+        for cost in [1, 7, 8, 1001] {
+            let deposits: Vec<u64> = weights
+                .iter()
+                .enumerate()
+                .map(|(i, weight)| required_deposit(cost, 2, *weight, 4, Some(i)))
+                .collect();
+            assert_eq!(deposits.iter().sum::<u64>(), cost);
+        }
+        assert_eq!(required_deposit(1, 2, 1, 4, Some(0)), 1);
+        assert_eq!(required_deposit(1, 2, 3, 4, Some(1)), 0);
+        // Zero, one and two units of dust between three members
+        for cost in [100, 101, 102] {
+            let sum: u64 = (0..3).map(|i| required_deposit(cost, 3, 1, 3, Some(i))).sum();
+            assert_eq!(sum, cost);
+        }
+    }
+
+    #[test]
+    fn required_deposit_of_an_empty_group_is_zero() {
+        assert_eq!(required_deposit(1000, 0, 1, 0, Some(0)), 0);
+        assert_eq!(required_deposit(1000, 0, 1, 1, None), 0);
+    }
+
+    #[test]
+    fn validate_approvers_needs_a_reachable_threshold() {
+        let (a, b) = (Pubkey::new_unique(), Pubkey::new_unique());
+        assert!(validate_approvers(&[], 0).is_ok());
+        assert!(validate_approvers(&[a, b], 2).is_ok());
+        assert!(validate_approvers(&[a, b], 3).is_err());
+        assert!(validate_approvers(&[a, b], 0).is_err());
+        assert!(validate_approvers(&[a, a], 1).is_err());
+    }
+
+    #[test]
+    fn validate_name_trims_and_rejects_unprintable_names() {
+        assert_eq!(validate_name("  Netflix  ").unwrap(), "Netflix");
+        assert!(validate_name("").is_err());
+        assert!(validate_name("   ").is_err());
+        assert!(validate_name("Net\nflix").is_err());
+        assert!(validate_name(&"x".repeat(MAX_NAME_LEN + 1)).is_err());
+        // Surrounding whitespace doesn't count toward the limit
+        assert!(validate_name(&format!(" {} ", "x".repeat(MAX_NAME_LEN))).is_ok());
+    }
+
+    #[test]
+    fn validate_due_bounds_the_due_time() {
+        let now = 1_700_000_000;
+        assert!(validate_due(now + 60, 0, now).is_ok());
+        assert!(validate_due(now + MAX_DUE_AHEAD, 0, now).is_ok());
+        assert!(validate_due(-1, 0, now).is_err());
+        assert!(validate_due(now + MAX_DUE_AHEAD + 1, 0, now).is_err());
+        assert!(validate_due(now + 60, i64::MAX, now).is_err());
+    }
+
+    #[test]
+    fn required_deposit_does_not_overflow() {
+        assert_eq!(required_deposit(u64::MAX, 1, 1, 1, Some(0)), u64::MAX);
+        assert_eq!(required_deposit(u64::MAX, 1, u16::MAX, 1, Some(0)), u64::MAX);
+    }
+}
@@ -1,162 +1,4339 @@
 import * as anchor from '@coral-xyz/anchor';
 import { Program } from '@coral-xyz/anchor';
 import { SplitFunds } from '../target/types/split_funds';
+import { MockYield } from '../target/types/mock_yield';
 import { assert } from 'chai';
 import {
   TOKEN_PROGRAM_ID,
   createMint,
   createAccount,
+  getAccount,
   mintTo,
+  transferChecked,
 } from '@solana/spl-token';
 
+// Configure Anchor provider
+const provider = anchor.AnchorProvider.env();
+anchor.setProvider(provider);
+const program = anchor.workspace.SplitFunds as Program<SplitFunds>;
+const owner = provider.wallet as anchor.Wallet;
+
+// Sleeps until the given unix timestamp (plus a second of slack) has passed
+const sleepUntil = async (timestamp: anchor.BN) => {
+  const ms = (timestamp.toNumber() + 1) * 1000 - Date.now();
+  if (ms > 0) await new Promise((resolve) => setTimeout(resolve, ms));
+};
+
+// Returns a unix timestamp the given number of seconds from now
+const fromNow = (seconds: number) =>
+  new anchor.BN(Math.floor(Date.now() / 1000) + seconds);
+
+// Airdrops SOL and waits for it to land so the wallet can pay rent
+const airdrop = async (to: anchor.web3.PublicKey) => {
+  const sig = await provider.connection.requestAirdrop(
+    to,
+    anchor.web3.LAMPORTS_PER_SOL,
+  );
+  const latest = await provider.connection.getLatestBlockhash();
+  await provider.connection.confirmTransaction({ signature: sig, ...latest });
+};
+
+// Asserts that the promise rejects with an error mentioning `code`
+const expectError = async (promise: Promise<unknown>, code: string) => {
+  try {
+    await promise;
+  } catch (err) {
+    assert.include(err.toString(), code);
+    return;
+  }
+  assert.fail(`expected ${code}`);
+};
+
+interface GroupFixture {
+  group: anchor.web3.PublicKey;
+  groupBump: number;
+  escrow: anchor.web3.PublicKey;
+  escrowBump: number;
+  vault: anchor.web3.PublicKey;
+  mint: anchor.web3.PublicKey;
+  ownerTokenAccount: anchor.web3.PublicKey;
+  feeRecipient: anchor.web3.PublicKey;
+  feeTokenAccount: anchor.web3.PublicKey;
+  due: anchor.BN;
+  createSignature: string;
+}
+
+interface MemberFixture {
+  authority: anchor.web3.Keypair;
+  account: anchor.web3.PublicKey;
+  tokenAccount: anchor.web3.PublicKey;
+}
+
+// Creates a mint along with the owner's and fee recipient's token accounts
+const createMintAccounts = async (feeRecipient: anchor.web3.PublicKey) => {
+  const mint = await createMint(
+    provider.connection,
+    owner.payer,
+    owner.publicKey,
+    null,
+    0, // decimals
+  );
+  const ownerTokenAccount = await createAccount(
+    provider.connection,
+    owner.payer,
+    mint,
+    owner.publicKey,
+  );
+  const feeTokenAccount = await createAccount(
+    provider.connection,
+    owner.payer,
+    mint,
+    feeRecipient,
+  );
+  return { mint, ownerTokenAccount, feeTokenAccount };
+};
+
+// Creates the escrow and vault PDAs collecting one mint for a group
+const initEscrow = async (
+  group: anchor.web3.PublicKey,
+  mint: anchor.web3.PublicKey,
+) => {
+  const [escrow, escrowBump] = anchor.web3.PublicKey.findProgramAddressSync(
+    [group.toBuffer(), mint.toBuffer()],
+    program.programId,
+  );
+  const [vault] = anchor.web3.PublicKey.findProgramAddressSync(
+    [Buffer.from('vault'), group.toBuffer(), mint.toBuffer()],
+    program.programId,
+  );
+  await program.methods
+    .initializeEscrow()
+    .accounts({
+      group,
+      escrow,
+      mint,
+      escrowTokenAccount: vault,
+      owner: owner.publicKey,
+      tokenProgram: TOKEN_PROGRAM_ID,
+      systemProgram: anchor.web3.SystemProgram.programId,
+    } as any)
+    .rpc();
+  return { escrow, escrowBump, vault };
+};
+
+// Derives a group's address and canonical bump from its owner, name and nonce.
+// A seed holds at most 32 bytes, so the name is passed as two seeds, which hash
+// the same as one.
+const findGroupPda = (
+  name: string,
+  nonce: anchor.BN,
+  groupOwner = owner.publicKey,
+) => {
+  // The program trims the name before deriving the address
+  const bytes = Buffer.from(name.trim()).subarray(0, 64);
+  return anchor.web3.PublicKey.findProgramAddressSync(
+    [
+      Buffer.from('group'),
+      groupOwner.toBuffer(),
+      bytes.subarray(0, 32),
+      bytes.subarray(32),
+      nonce.toArrayLike(Buffer, 'le', 8),
+    ],
+    program.programId,
+  );
+};
+
+const groupPda = (
+  name: string,
+  nonce: anchor.BN,
+  groupOwner = owner.publicKey,
+) => findGroupPda(name, nonce, groupOwner)[0];
+
+// The nonce an owner's next group has to be created with
+const nextNonce = async (groupOwner = owner.publicKey) => {
+  const [counter] = anchor.web3.PublicKey.findProgramAddressSync(
+    [Buffer.from('owner-counter'), groupOwner.toBuffer()],
+    program.programId,
+  );
+  const data = await program.account.ownerCounter.fetchNullable(counter);
+  return data ? data.nextNonce : new anchor.BN(0);
+};
+
+// Default names are numbered to tell groups apart in logs
+let groupCounter = 0;
+
+// Pads a display symbol to the eight bytes a group stores
+const symbolBytes = (symbol: string) => {
+  const bytes = Array(8).fill(0);
+  bytes.splice(0, symbol.length, ...Buffer.from(symbol));
+  return bytes;
+};
+
+// Creates a mint, a group using it, and the group's escrow
+const setupGroup = async ({
+  name = `Test Group ${++groupCounter}`,
+  totalCost = 1000 as number | anchor.BN,
+  due = fromNow(20),
+  grace = 0 as number | anchor.BN,
+  allowPartial = false,
+  // Null takes the program config's default fee
+  feeBps = 0 as number | null,
+  minMembers = 1,
+  maxMembers = 255,
+  recipients = [] as { pubkey: anchor.web3.PublicKey; bps: number }[],
+  payoutRecipient = null as anchor.web3.PublicKey | null,
+  lateFeeBps = 0,
+  inviteOnly = false,
+  maxPerMember = 0,
+  allowLateJoin = false,
+  streamPayouts = false,
+  minDeposit = 0,
+  category = 0,
+  yieldEnabled = false,
+  yieldProgram = anchor.web3.PublicKey.default,
+  // Accepted besides the group's own mint; leaving it empty accepts any mint
+  extraMints = [] as anchor.web3.PublicKey[],
+  allowCredit = false,
+  restrictPayout = false,
+  symbol = '',
+  decimalsHint = 0,
+  approvers = [] as anchor.web3.PublicKey[],
+  threshold = 0,
+  approvalAbove = 0,
+  settlementDelay = 0,
+  // Defaults to the owner's next nonce
+  nonce = null as anchor.BN | null,
+  refundPenaltyBps = 0,
+  // Zero leaves deposits open until the due time
+  depositDeadline = 0 as number | anchor.BN,
+  rejectEmptyPayout = false,
+} = {}): Promise<GroupFixture> => {
+  const feeRecipient = anchor.web3.Keypair.generate().publicKey;
+  const { mint, ownerTokenAccount, feeTokenAccount } =
+    await createMintAccounts(feeRecipient);
+
+  nonce ??= await nextNonce();
+  const [group, groupBump] = findGroupPda(name, nonce);
+  const createSignature = await program.methods
+    .createGroup({
+      groupName: name,
+      totalCost: new anchor.BN(totalCost),
+      subscriptionDue: due,
+      isNative: false,
+      gracePeriod: new anchor.BN(grace),
+      allowPartial,
+      feeBps,
+      feeRecipient,
+      minMembers,
+      maxMembers,
+      recipients,
+      payoutRecipient,
+      lateFeeBps,
+      inviteOnly,
+      maxPerMember: new anchor.BN(maxPerMember),
+      allowLateJoin,
+      streamPayouts,
+      minDeposit: new anchor.BN(minDeposit),
+      category,
+      yieldEnabled,
+      yieldProgram,
+      allowedMints: extraMints.length ? [mint, ...extraMints] : [],
+      allowCredit,
+      restrictPayout,
+      symbol: symbolBytes(symbol),
+      decimalsHint,
+      approvers,
+      threshold,
+      approvalAbove: new anchor.BN(approvalAbove),
+      settlementDelay: new anchor.BN(settlementDelay),
+      nonce,
+      refundPenaltyBps,
+      depositDeadline: new anchor.BN(depositDeadline),
+      rejectEmptyPayout,
+    })
+    .accounts({
+      group,
+      mint,
+      owner: owner.publicKey,
+      systemProgram: anchor.web3.SystemProgram.programId,
+    } as any)
+    .rpc();
+
+  const { escrow, escrowBump, vault } = await initEscrow(group, mint);
+
+  return {
+    group,
+    groupBump,
+    escrow,
+    escrowBump,
+    vault,
+    mint,
+    ownerTokenAccount,
+    feeRecipient,
+    feeTokenAccount,
+    due,
+    createSignature,
+  };
+};
+
+// Makes a group accept a new mint, returning a fixture targeting its escrow
+const addEscrow = async (fixture: GroupFixture): Promise<GroupFixture> => {
+  const accounts = await createMintAccounts(fixture.feeRecipient);
+  const escrow = await initEscrow(fixture.group, accounts.mint);
+  return { ...fixture, ...accounts, ...escrow };
+};
+
+// Derives the membership PDA of a wallet in a group
+const memberPda = (fixture: GroupFixture, authority: anchor.web3.Keypair) =>
+  anchor.web3.PublicKey.findProgramAddressSync(
+    [fixture.group.toBuffer(), authority.publicKey.toBuffer()],
+    program.programId,
+  )[0];
+
+// Joins `authority` to the group; invite-only groups need the owner's signature
+const invite = (
+  fixture: GroupFixture,
+  authority: anchor.web3.Keypair,
+  weight = 1,
+  inviter: anchor.web3.PublicKey | null = null,
+  memberDue: anchor.BN | null = null,
+) =>
+  program.methods
+    .inviteMember(weight, memberDue)
+    .accounts({
+      group: fixture.group,
+      member: memberPda(fixture, authority),
+      memberAuthority: authority.publicKey,
+      owner: inviter,
+      payer: authority.publicKey,
+      systemProgram: anchor.web3.SystemProgram.programId,
+    } as any)
+    .signers([authority])
+    .rpc();
+
+// Invites a fresh, funded wallet holding `balance` tokens into the group
+const addMember = async (
+  fixture: GroupFixture,
+  balance = 1000,
+  weight = 1,
+): Promise<MemberFixture> => {
+  const authority = anchor.web3.Keypair.generate();
+  await airdrop(authority.publicKey);
+
+  const tokenAccount = await createAccount(
+    provider.connection,
+    owner.payer,
+    fixture.mint,
+    authority.publicKey,
+  );
+  await mintTo(
+    provider.connection,
+    owner.payer,
+    fixture.mint,
+    tokenAccount,
+    owner.publicKey,
+    balance,
+  );
+
+  await invite(fixture, authority, weight);
+  return { authority, account: memberPda(fixture, authority), tokenAccount };
+};
+
+// Deposits into the escrow; streaming groups also need the payout token account
+const deposit = (
+  fixture: GroupFixture,
+  member: MemberFixture,
+  amount: number | anchor.BN,
+  payoutTokenAccount: anchor.web3.PublicKey | null = null,
+) =>
+  program.methods
+    .depositFunds(new anchor.BN(amount))
+    .accounts({
+      group: fixture.group,
+      member: member.account,
+      memberAuthority: member.authority.publicKey,
+      mint: fixture.mint,
+      fromTokenAccount: member.tokenAccount,
+      escrowTokenAccount: fixture.vault,
+      escrow: fixture.escrow,
+      payoutTokenAccount,
+      tokenProgram: TOKEN_PROGRAM_ID,
+    } as any)
+    .signers([member.authority])
+    .rpc();
+
+const refund = (fixture: GroupFixture, member: MemberFixture) =>
+  program.methods
+    .refundMember()
+    .accounts({
+      group: fixture.group,
+      member: member.account,
+      memberAuthority: member.authority.publicKey,
+      mint: fixture.mint,
+      memberTokenAccount: member.tokenAccount,
+      escrowTokenAccount: fixture.vault,
+      escrow: fixture.escrow,
+      tokenProgram: TOKEN_PROGRAM_ID,
+    } as any)
+    .signers([member.authority])
+    .rpc();
+
+const leave = (fixture: GroupFixture, member: MemberFixture) =>
+  program.methods
+    .leaveGroup()
+    .accounts({
+      group: fixture.group,
+      member: member.account,
+      memberAuthority: member.authority.publicKey,
+      mint: fixture.mint,
+      memberTokenAccount: member.tokenAccount,
+      escrowTokenAccount: fixture.vault,
+      escrow: fixture.escrow,
+      tokenProgram: TOKEN_PROGRAM_ID,
+    } as any)
+    .signers([member.authority])
+    .rpc();
+
+// Pays out the escrow; co-recipients' token accounts are passed in order,
+// and the settler is only needed by groups that restrict payouts
+const payout = (
+  fixture: GroupFixture,
+  recipientAccounts: anchor.web3.PublicKey[] = [],
+  settler: anchor.web3.PublicKey | null = null,
+) =>
+  program.methods
+    .executePayout()
+    .accounts({
+      group: fixture.group,
+      escrow: fixture.escrow,
+      mint: fixture.mint,
+      escrowTokenAccount: fixture.vault,
+      ownerTokenAccount: fixture.ownerTokenAccount,
+      feeRecipientTokenAccount: fixture.feeTokenAccount,
+      settler,
+      tokenProgram: TOKEN_PROGRAM_ID,
+    } as any)
+    .remainingAccounts(
+      recipientAccounts.map((pubkey) => ({
+        pubkey,
+        isWritable: true,
+        isSigner: false,
+      })),
+    )
+    .rpc();
+
+// Decodes the program events emitted by a confirmed transaction
+const eventsOf = async (signature: string) => {
+  const latest = await provider.connection.getLatestBlockhash();
+  await provider.connection.confirmTransaction(
+    { signature, ...latest },
+    'confirmed',
+  );
+  const tx = await provider.connection.getTransaction(signature, {
+    commitment: 'confirmed',
+    maxSupportedTransactionVersion: 0,
+  });
+  const parser = new anchor.EventParser(program.programId, program.coder);
+  return [...parser.parseLogs(tx.meta.logMessages)];
+};
+
+// Reads the u64 an instruction returned through return data
+const returnedU64 = async (signature: string) => {
+  const latest = await provider.connection.getLatestBlockhash();
+  await provider.connection.confirmTransaction(
+    { signature, ...latest },
+    'confirmed',
+  );
+  const tx = await provider.connection.getTransaction(signature, {
+    commitment: 'confirmed',
+    maxSupportedTransactionVersion: 0,
+  });
+  const [data] = tx.meta.returnData.data;
+  return new anchor.BN(Buffer.from(data, 'base64'), 'le').toNumber();
+};
+
+const tokenBalance = async (account: anchor.web3.PublicKey) =>
+  Number((await getAccount(provider.connection, account)).amount);
+
 describe('split_funds', () => {
-  // Configure Anchor provider
-  const provider = anchor.AnchorProvider.env();
-  anchor.setProvider(provider);
-  const program = anchor.workspace.SplitFunds as Program<SplitFunds>;
-
-  let groupAccount: anchor.web3.Keypair;
-  let memberAccount: anchor.web3.Keypair;
-  let escrowAccount: anchor.web3.Keypair;
-  let mint: anchor.web3.PublicKey;
-  let memberTokenAccount: anchor.web3.PublicKey;
-  let escrowTokenAccount: anchor.web3.PublicKey;
-  let ownerTokenAccount: anchor.web3.PublicKey;
-
-  const owner = provider.wallet as anchor.Wallet;
-  const member = anchor.web3.Keypair.generate();
+  let fixture: GroupFixture;
+  let member: MemberFixture;
+  let others: MemberFixture[];
+
+  const groupName = 'Test Group';
+  // 1000 split three ways; the first member invited owes the extra unit
+  const firstShare = 334;
+  const share = 333;
+
+  it('Creates a group and its escrow', async () => {
+    fixture = await setupGroup({ name: groupName });
+
+    const group = await program.account.groupAccount.fetch(fixture.group);
+    assert.equal(group.groupName, groupName);
+    assert.ok(group.isActive);
+    assert.equal(group.bump, fixture.groupBump);
+
+    const escrow = await program.account.escrowAccount.fetch(fixture.escrow);
+    assert.ok(escrow.group.equals(fixture.group));
+    assert.equal(escrow.totalHeld.toNumber(), 0);
+    assert.equal(escrow.bump, fixture.escrowBump);
+    assert.ok(escrow.vault.equals(fixture.vault));
+
+    const vault = await getAccount(provider.connection, fixture.vault);
+    assert.ok(vault.owner.equals(fixture.escrow));
+  });
+
+  it('Invites a member', async () => {
+    member = await addMember(fixture);
+
+    const memberData = await program.account.memberAccount.fetch(
+      member.account,
+    );
+    assert.equal(memberData.hasPaid, false);
+  });
+
+  it('Counts every invited member', async () => {
+    others = [await addMember(fixture), await addMember(fixture)];
+
+    const group = await program.account.groupAccount.fetch(fixture.group);
+    assert.equal(group.memberCount, 3);
+  });
+
+  it('Rejects inviting the same wallet twice', async () => {
+    await expectError(invite(fixture, member.authority), 'already in use');
+
+    const group = await program.account.groupAccount.fetch(fixture.group);
+    assert.equal(group.memberCount, 3);
+  });
+
+  it('Rejects an overpayment', async () => {
+    await expectError(
+      deposit(fixture, member, firstShare + 1),
+      'ExceedsShare',
+    );
+  });
+
+  it('Accumulates partial deposits toward the share', async () => {
+    await deposit(fixture, member, 100);
+    let memberData = await program.account.memberAccount.fetch(member.account);
+    assert.equal(memberData.contributed.toNumber(), 100);
+    assert.equal(memberData.hasPaid, false);
+
+    // A top-up past the share is rejected without touching the balance
+    await expectError(deposit(fixture, member, firstShare), 'ExceedsShare');
+
+    await deposit(fixture, member, firstShare - 100);
+    memberData = await program.account.memberAccount.fetch(member.account);
+    assert.equal(memberData.contributed.toNumber(), firstShare);
+    assert.equal(memberData.hasPaid, true);
+  });
+
+  it('Refunds a member before the due time', async () => {
+    await refund(fixture, member);
+
+    const memberData = await program.account.memberAccount.fetch(
+      member.account,
+    );
+    assert.equal(memberData.hasPaid, false);
+    assert.equal(memberData.contributed.toNumber(), 0);
+
+    const escrow = await program.account.escrowAccount.fetch(fixture.escrow);
+    assert.equal(escrow.totalHeld.toNumber(), 0);
+    assert.equal(await tokenBalance(member.tokenAccount), 1000);
+
+    // The group's books follow the escrow's
+    const group = await program.account.groupAccount.fetch(fixture.group);
+    assert.equal(group.paidCount, 0);
+    assert.equal(group.totalHeld.toNumber(), 0);
+  });
+
+  it('Rejects a second refund', async () => {
+    await expectError(refund(fixture, member), 'NothingToRefund');
+
+    // Deposit again so the payout below has funds to move
+    await deposit(fixture, member, firstShare);
+  });
+
+  it('Executes payout after due time', async () => {
+    for (const m of others) await deposit(fixture, m, share);
+
+    // Wait until subscription_due passes
+    await sleepUntil(fixture.due);
+    await payout(fixture);
+
+    const group = await program.account.groupAccount.fetch(fixture.group);
+    assert.equal(group.isActive, false);
+    assert.equal(group.paidCount, 3);
+    assert.equal(await tokenBalance(fixture.ownerTokenAccount), 1000);
+
+    // The payout time is recorded for auditing
+    const paidOutAt = group.paidOutAt.toNumber();
+    assert.isAtLeast(paidOutAt, fixture.due.toNumber());
+    assert.isAtMost(paidOutAt, Math.floor(Date.now() / 1000) + 10);
+  });
+
+  it('Rejects a second payout', async () => {
+    await expectError(payout(fixture), 'AlreadyPaidOut');
+    assert.equal(await tokenBalance(fixture.ownerTokenAccount), 1000);
+  });
+
+  it('Rejects an invite after payout', async () => {
+    const late = anchor.web3.Keypair.generate();
+    await airdrop(late.publicKey);
+    await expectError(invite(fixture, late), 'InactiveGroup');
+  });
+});
+
+describe('cancel_group', () => {
+  let fixture: GroupFixture;
+  let members: MemberFixture[];
+
+  before(async () => {
+    fixture = await setupGroup({ due: fromNow(5) });
+    members = [await addMember(fixture), await addMember(fixture)];
+    for (const m of members) await deposit(fixture, m, 500);
+  });
+
+  const cancel = () =>
+    program.methods
+      .cancelGroup()
+      .accounts({ group: fixture.group, owner: owner.publicKey } as any)
+      .rpc();
+
+  it('Rejects cancellation by anyone but the owner', async () => {
+    const stranger = members[0].authority;
+    await expectError(
+      program.methods
+        .cancelGroup()
+        .accounts({ group: fixture.group, owner: stranger.publicKey } as any)
+        .signers([stranger])
+        .rpc(),
+      'ConstraintHasOne',
+    );
+  });
+
+  it('Cancels the group and blocks new deposits', async () => {
+    await cancel();
+
+    const group = await program.account.groupAccount.fetch(fixture.group);
+    assert.equal(group.isActive, false);
+    assert.equal(group.isCancelled, true);
+
+    await refund(fixture, members[0]);
+    await expectError(deposit(fixture, members[0], 500), 'GroupCancelled');
+  });
+
+  it('Lets every member refund after cancellation', async () => {
+    // Refunds stay open past the due time once the group is cancelled
+    await sleepUntil(fixture.due);
+    await refund(fixture, members[1]);
+
+    for (const m of members) {
+      assert.equal(await tokenBalance(m.tokenAccount), 1000);
+    }
+    const escrow = await program.account.escrowAccount.fetch(fixture.escrow);
+    assert.equal(escrow.totalHeld.toNumber(), 0);
+  });
+
+  it('Blocks payout of a cancelled group', async () => {
+    await expectError(payout(fixture), 'GroupCancelled');
+  });
+});
+
+describe('refund_all', () => {
+  let fixture: GroupFixture;
+  let members: MemberFixture[];
+
+  // Passes each member account along with its token account, in pairs
+  const refundAll = (accounts = members) =>
+    program.methods
+      .refundAll()
+      .accounts({
+        group: fixture.group,
+        escrow: fixture.escrow,
+        mint: fixture.mint,
+        escrowTokenAccount: fixture.vault,
+        owner: owner.publicKey,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      } as any)
+      .remainingAccounts(
+        accounts.flatMap((m) => [
+          { pubkey: m.account, isWritable: true, isSigner: false },
+          { pubkey: m.tokenAccount, isWritable: true, isSigner: false },
+        ]),
+      )
+      .rpc();
+
+  before(async () => {
+    fixture = await setupGroup({ totalCost: 300 });
+    members = [];
+    for (let i = 0; i < 3; i++) members.push(await addMember(fixture));
+    for (const m of members) await deposit(fixture, m, 100);
+  });
+
+  it('Rejects pushing refunds before the group is cancelled', async () => {
+    await expectError(refundAll(), 'NotCancelled');
+  });
+
+  it('Refunds every member of a cancelled group in one call', async () => {
+    await program.methods
+      .cancelGroup()
+      .accounts({ group: fixture.group, owner: owner.publicKey } as any)
+      .rpc();
+    await refundAll();
+
+    for (const m of members) {
+      assert.equal(await tokenBalance(m.tokenAccount), 1000);
+      const data = await program.account.memberAccount.fetch(m.account);
+      assert.equal(data.contributed.toNumber(), 0);
+      assert.equal(data.hasPaid, false);
+    }
+    const escrow = await program.account.escrowAccount.fetch(fixture.escrow);
+    assert.equal(escrow.totalHeld.toNumber(), 0);
+  });
+
+  it('Skips members already refunded so calls can resume', async () => {
+    await refundAll();
+    for (const m of members) {
+      assert.equal(await tokenBalance(m.tokenAccount), 1000);
+    }
+  });
+});
+
+describe('create_group validation', () => {
+  it('Rejects a name longer than 64 bytes', async () => {
+    await expectError(setupGroup({ name: 'x'.repeat(65) }), 'NameTooLong');
+  });
+
+  it('Rejects an empty or blank name', async () => {
+    await expectError(setupGroup({ name: '' }), 'InvalidName');
+    await expectError(setupGroup({ name: '   ' }), 'InvalidName');
+  });
+
+  it('Rejects a name with control characters', async () => {
+    await expectError(setupGroup({ name: 'Bad\u0007Name' }), 'InvalidName');
+  });
+
+  it('Stores the name without surrounding whitespace', async () => {
+    const fixture = await setupGroup({ name: `  Padded ${++groupCounter}  ` });
+    const group = await program.account.groupAccount.fetch(fixture.group);
+    assert.equal(group.groupName, `Padded ${groupCounter}`);
+  });
+
+  it('Accepts a name of exactly 64 bytes', async () => {
+    const fixture = await setupGroup({ name: 'x'.repeat(64) });
+    const group = await program.account.groupAccount.fetch(fixture.group);
+    assert.equal(group.groupName.length, 64);
+  });
+
+  it('Creates the group at the address derived from its name', async () => {
+    const nonce = await nextNonce();
+    const fixture = await setupGroup({ name: 'Derived Group' });
+    assert.ok(fixture.group.equals(groupPda('Derived Group', nonce)));
+
+    const group = await program.account.groupAccount.fetch(fixture.group);
+    assert.ok(group.owner.equals(owner.publicKey));
+    assert.equal(group.groupName, 'Derived Group');
+    assert.ok(group.nonce.eq(nonce));
+    assert.ok((await nextNonce()).eq(nonce.addn(1)));
+  });
+
+  it('Creates groups of the same name under different nonces', async () => {
+    const first = await setupGroup({ name: 'Same Name' });
+    const second = await setupGroup({ name: 'Same Name' });
+    assert.notOk(first.group.equals(second.group));
+
+    const [a, b] = await Promise.all(
+      [first, second].map((f) => program.account.groupAccount.fetch(f.group)),
+    );
+    assert.equal(a.groupName, b.groupName);
+    assert.ok(b.nonce.eq(a.nonce.addn(1)));
+  });
+
+  it("Rejects a nonce other than the owner's next one", async () => {
+    const used = (await nextNonce()).subn(1);
+    await expectError(
+      setupGroup({ name: 'Same Name', nonce: used }),
+      'WrongNonce',
+    );
+  });
+
+  it('Rejects a due time in the past', async () => {
+    await expectError(setupGroup({ due: fromNow(-60) }), 'DueInPast');
+  });
+
+  it('Rejects a negative due time', async () => {
+    await expectError(
+      setupGroup({ due: new anchor.BN(-1) }),
+      'InvalidDueDate',
+    );
+  });
+
+  it('Rejects a due time too far ahead', async () => {
+    const sixYears = 6 * 365 * 24 * 60 * 60;
+    await expectError(
+      setupGroup({ due: fromNow(sixYears) }),
+      'InvalidDueDate',
+    );
+  });
+
+  it('Rejects a grace period that overflows the due time', async () => {
+    const i64Max = new anchor.BN('9223372036854775807');
+    await expectError(setupGroup({ grace: i64Max }), 'InvalidDueDate');
+  });
+
+  it('Rejects a zero total cost', async () => {
+    await expectError(setupGroup({ totalCost: 0 }), 'ZeroCost');
+  });
+
+  it('Rejects a total cost above the cap', async () => {
+    const maxTotalCost = new anchor.BN('1000000000000000');
+    await expectError(
+      setupGroup({ totalCost: maxTotalCost.addn(1) }),
+      'CostTooHigh',
+    );
+    await setupGroup({ totalCost: maxTotalCost });
+  });
+
+  it('Rejects a fee above 100%', async () => {
+    await expectError(setupGroup({ feeBps: 10_001 }), 'InvalidFee');
+  });
+
+  it('Rejects a minimum member count above the maximum', async () => {
+    await expectError(
+      setupGroup({ minMembers: 3, maxMembers: 2 }),
+      'InvalidMemberBounds',
+    );
+  });
+});
+
+describe('categories', () => {
+  // Discriminants of GroupCategory
+  const streaming = 1;
+  const gym = 3;
+
+  it('Records the category on the group and in GroupCreated', async () => {
+    for (const category of [streaming, gym]) {
+      const fixture = await setupGroup({ category });
+      const group = await program.account.groupAccount.fetch(fixture.group);
+      assert.equal(group.category, category);
+
+      const [created] = await eventsOf(fixture.createSignature);
+      assert.equal(created.name, 'groupCreated');
+      assert.equal(created.data.category, category);
+    }
+  });
+
+  it('Rejects an unknown category', async () => {
+    await expectError(setupGroup({ category: 6 }), 'InvalidCategory');
+  });
+});
+
+describe('display metadata', () => {
+  it('Round-trips the symbol and decimals hint', async () => {
+    const fixture = await setupGroup({ symbol: 'USD', decimalsHint: 2 });
+    const group = await program.account.groupAccount.fetch(fixture.group);
+    assert.deepEqual(group.symbol, symbolBytes('USD'));
+    assert.equal(group.decimalsHint, 2);
+
+    const [created] = await eventsOf(fixture.createSignature);
+    assert.deepEqual(created.data.symbol, symbolBytes('USD'));
+    assert.equal(created.data.decimalsHint, 2);
+  });
+});
+
+describe('escrow and member binding', () => {
+  let groupA: GroupFixture;
+  let groupB: GroupFixture;
+  let memberA: MemberFixture;
+  let memberB: MemberFixture;
+
+  before(async () => {
+    groupA = await setupGroup({ totalCost: 500, due: fromNow(5) });
+    groupB = await setupGroup({ totalCost: 500, due: fromNow(5) });
+    memberA = await addMember(groupA);
+    memberB = await addMember(groupB);
+  });
+
+  it('Rejects a deposit into another group\'s escrow', async () => {
+    await expectError(
+      deposit({ ...groupA, escrow: groupB.escrow }, memberA, 500),
+      'ConstraintHasOne',
+    );
+  });
+
+  it('Rejects a deposit from another group\'s member', async () => {
+    await expectError(deposit(groupA, memberB, 500), 'ConstraintHasOne');
+  });
+
+  it('Rejects a deposit signed by another wallet', async () => {
+    const impostor = await addMember(groupA);
+    await expectError(
+      deposit(groupA, { ...impostor, account: memberA.account }, 500),
+      'Unauthorized',
+    );
+  });
+
+  it('Rejects a payout from another group\'s escrow', async () => {
+    await deposit(groupB, memberB, 500);
+    await sleepUntil(groupA.due);
+    await expectError(
+      payout({ ...groupA, escrow: groupB.escrow, vault: groupB.vault }),
+      'ConstraintHasOne',
+    );
+  });
+});
+
+describe('mint consistency', () => {
+  let fixture: GroupFixture;
+  let other: GroupFixture;
+  let member: MemberFixture;
+  let foreign: MemberFixture;
+
+  before(async () => {
+    fixture = await setupGroup();
+    other = await setupGroup();
+    member = await addMember(fixture);
+    // Holds tokens of the other group's mint
+    foreign = await addMember(other);
+  });
+
+  it('Stores the mint on the group', async () => {
+    const group = await program.account.groupAccount.fetch(fixture.group);
+    assert.ok(group.mint.equals(fixture.mint));
+  });
+
+  it('Rejects a deposit declaring a different mint', async () => {
+    await expectError(
+      deposit(
+        { ...fixture, mint: other.mint },
+        { ...member, tokenAccount: foreign.tokenAccount },
+        500,
+      ),
+      'WrongMint',
+    );
+  });
+
+  it('Rejects a deposit from a token account of a different mint', async () => {
+    await expectError(
+      deposit(fixture, { ...member, tokenAccount: foreign.tokenAccount }, 500),
+      'ConstraintTokenMint',
+    );
+  });
+
+  it('Moves tokens with the mint\'s decimals checked', async () => {
+    // The token program refuses a checked transfer stating the wrong decimals
+    // (MintDecimalsMismatch)...
+    await expectError(
+      transferChecked(
+        provider.connection,
+        owner.payer,
+        member.tokenAccount,
+        fixture.mint,
+        fixture.vault,
+        member.authority,
+        100,
+        6,
+      ),
+      '0x12',
+    );
+
+    // ...while the program passes the mint's own decimals through
+    await deposit(fixture, member, 100);
+    assert.equal(await tokenBalance(fixture.vault), 100);
+  });
+
+  it('Rejects a deposit into a token account besides the vault', async () => {
+    // Same mint as the vault, but controlled by the attacker
+    const rogue = await createAccount(
+      provider.connection,
+      owner.payer,
+      fixture.mint,
+      member.authority.publicKey,
+      anchor.web3.Keypair.generate(),
+    );
+    await expectError(
+      deposit({ ...fixture, vault: rogue }, member, 500),
+      'WrongVault',
+    );
+  });
+});
+
+describe('events', () => {
+  it('Carries a deposit memo in MemberDeposited', async () => {
+    const fixture = await setupGroup({ totalCost: 100 });
+    const member = await addMember(fixture);
+    const memo = [...Buffer.from('INV-2024-0042'.padEnd(32, '\0'))];
+    const signature = await program.methods
+      .depositFundsWithMemo(new anchor.BN(40), memo)
+      .accounts({
+        group: fixture.group,
+        member: member.account,
+        memberAuthority: member.authority.publicKey,
+        mint: fixture.mint,
+        fromTokenAccount: member.tokenAccount,
+        escrowTokenAccount: fixture.vault,
+        escrow: fixture.escrow,
+        payoutTokenAccount: null,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      } as any)
+      .signers([member.authority])
+      .rpc();
+
+    const [event] = await eventsOf(signature);
+    assert.equal(event.name, 'memberDeposited');
+    assert.deepEqual(event.data.memo, memo);
+    assert.equal(event.data.amount.toNumber(), 40);
+
+    // Plain deposits carry an empty memo
+    const [plain] = await eventsOf(await deposit(fixture, member, 60));
+    assert.deepEqual(plain.data.memo, Array(32).fill(0));
+  });
+
+  it('Emits GroupCreated, MemberDeposited and PayoutExecuted', async () => {
+    const fixture = await setupGroup({ totalCost: 600, due: fromNow(8) });
+    const createdEvents = await eventsOf(fixture.createSignature);
+    assert.equal(createdEvents[0].name, 'groupCreated');
+    assert.ok(createdEvents[0].data.group.equals(fixture.group));
+    assert.ok(createdEvents[0].data.owner.equals(owner.publicKey));
+    assert.equal(createdEvents[0].data.totalCost.toNumber(), 600);
+    assert.ok(createdEvents[0].data.subscriptionDue.eq(fixture.due));
+
+    const member = await addMember(fixture);
+    const depositEvents = await eventsOf(await deposit(fixture, member, 600));
+    assert.equal(depositEvents[0].name, 'memberDeposited');
+    assert.ok(depositEvents[0].data.member.equals(member.authority.publicKey));
+    assert.equal(depositEvents[0].data.amount.toNumber(), 600);
+    assert.equal(depositEvents[0].data.totalHeld.toNumber(), 600);
+
+    await sleepUntil(fixture.due);
+    const payoutEvents = await eventsOf(await payout(fixture));
+    assert.equal(payoutEvents[0].name, 'payoutExecuted');
+    assert.ok(payoutEvents[0].data.group.equals(fixture.group));
+    assert.equal(payoutEvents[0].data.amount.toNumber(), 600);
+  });
+});
+
+describe('close_group', () => {
+  let fixture: GroupFixture;
+
+  const close = (vault: anchor.web3.PublicKey | null = fixture.vault) =>
+    program.methods
+      .closeGroup()
+      .accounts({
+        group: fixture.group,
+        escrow: fixture.escrow,
+        vault,
+        owner: owner.publicKey,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      } as any)
+      .rpc();
+
+  before(async () => {
+    fixture = await setupGroup({ totalCost: 500, due: fromNow(6) });
+    await deposit(fixture, await addMember(fixture), 500);
+  });
+
+  it('Rejects closing an active group', async () => {
+    await expectError(close(), 'GroupStillActive');
+  });
+
+  it('Closes the group, escrow and vault and refunds their rent', async () => {
+    await sleepUntil(fixture.due);
+    await payout(fixture);
+    // The vault can't be left behind once its escrow is gone
+    await expectError(close(null), 'WrongVault');
+
+    const rent = (
+      await Promise.all(
+        [fixture.group, fixture.escrow, fixture.vault].map((account) =>
+          provider.connection.getBalance(account),
+        ),
+      )
+    ).reduce((sum, lamports) => sum + lamports, 0);
+    const before = await provider.connection.getBalance(owner.publicKey);
+    await close();
+    const after = await provider.connection.getBalance(owner.publicKey);
+    // Everything comes back to the owner, less the transaction fee
+    assert.equal(after - before, rent - 5_000);
+
+    assert.isNull(await provider.connection.getAccountInfo(fixture.group));
+    assert.isNull(await provider.connection.getAccountInfo(fixture.escrow));
+    assert.isNull(await provider.connection.getAccountInfo(fixture.vault));
+  });
+});
+
+describe('native SOL groups', () => {
+  const cost = anchor.web3.LAMPORTS_PER_SOL / 10;
+  let group: anchor.web3.PublicKey;
+  let escrow: anchor.web3.PublicKey;
+  let member: anchor.web3.Keypair;
+  let due: anchor.BN;
+  const feeRecipient = anchor.web3.Keypair.generate().publicKey;
+
+  const depositSol = (amount: number) =>
+    program.methods
+      .depositSol(new anchor.BN(amount))
+      .accounts({
+        group,
+        member: memberPda({ group } as GroupFixture, member),
+        memberAuthority: member.publicKey,
+        escrow,
+        systemProgram: anchor.web3.SystemProgram.programId,
+      } as any)
+      .signers([member])
+      .rpc();
+
+  // Creates a native SOL group and its escrow
+  const createSolGroup = async (name: string, solDue: anchor.BN) => {
+    const nonce = await nextNonce();
+    const solGroup = groupPda(name, nonce);
+    await program.methods
+      .createGroup({
+        groupName: name,
+        totalCost: new anchor.BN(cost),
+        subscriptionDue: solDue,
+        isNative: true,
+        gracePeriod: new anchor.BN(0),
+        allowPartial: false,
+        feeBps: 0,
+        feeRecipient,
+        minMembers: 1,
+        maxMembers: 255,
+        recipients: [],
+        payoutRecipient: null,
+        lateFeeBps: 0,
+        inviteOnly: false,
+        maxPerMember: new anchor.BN(0),
+        allowLateJoin: false,
+        streamPayouts: false,
+        minDeposit: new anchor.BN(0),
+        category: 0,
+        yieldEnabled: false,
+        yieldProgram: anchor.web3.PublicKey.default,
+        allowedMints: [],
+        allowCredit: false,
+        restrictPayout: false,
+        symbol: symbolBytes(''),
+        decimalsHint: 0,
+        approvers: [],
+        threshold: 0,
+        approvalAbove: new anchor.BN(0),
+        settlementDelay: new anchor.BN(0),
+        nonce,
+        refundPenaltyBps: 0,
+        depositDeadline: new anchor.BN(0),
+        rejectEmptyPayout: false,
+      })
+      .accounts({
+        group: solGroup,
+        mint: null,
+        owner: owner.publicKey,
+        systemProgram: anchor.web3.SystemProgram.programId,
+      } as any)
+      .rpc();
+
+    // Native groups have no mint, so the escrow is keyed by the default pubkey
+    const [solEscrow] = anchor.web3.PublicKey.findProgramAddressSync(
+      [solGroup.toBuffer(), anchor.web3.PublicKey.default.toBuffer()],
+      program.programId,
+    );
+    await program.methods
+      .initializeSolEscrow()
+      .accounts({
+        group: solGroup,
+        escrow: solEscrow,
+        owner: owner.publicKey,
+        systemProgram: anchor.web3.SystemProgram.programId,
+      } as any)
+      .rpc();
+
+    return { group: solGroup, escrow: solEscrow };
+  };
+
+  before(async () => {
+    due = fromNow(8);
+    ({ group, escrow } = await createSolGroup('SOL Group', due));
+    member = anchor.web3.Keypair.generate();
+    await airdrop(member.publicKey);
+    await invite({ group } as GroupFixture, member);
+  });
+
+  it('Rejects a native group that names a mint', async () => {
+    const fixture = await setupGroup();
+    const nonce = await nextNonce();
+    await expectError(
+      program.methods
+        .createGroup({
+          groupName: 'Mixed',
+          totalCost: new anchor.BN(cost),
+          subscriptionDue: fromNow(20),
+          isNative: true,
+          gracePeriod: new anchor.BN(0),
+          allowPartial: false,
+          feeBps: 0,
+          feeRecipient,
+          minMembers: 1,
+          maxMembers: 255,
+          recipients: [],
+          payoutRecipient: null,
+          lateFeeBps: 0,
+          inviteOnly: false,
+          maxPerMember: new anchor.BN(0),
+          allowLateJoin: false,
+          streamPayouts: false,
+          minDeposit: new anchor.BN(0),
+          category: 0,
+          yieldEnabled: false,
+          yieldProgram: anchor.web3.PublicKey.default,
+          allowedMints: [],
+          allowCredit: false,
+          restrictPayout: false,
+          symbol: symbolBytes(''),
+          decimalsHint: 0,
+          approvers: [],
+          threshold: 0,
+          approvalAbove: new anchor.BN(0),
+          settlementDelay: new anchor.BN(0),
+          nonce,
+          refundPenaltyBps: 0,
+          depositDeadline: new anchor.BN(0),
+          rejectEmptyPayout: false,
+        })
+        .accounts({
+          group: groupPda('Mixed', nonce),
+          mint: fixture.mint,
+          owner: owner.publicKey,
+          systemProgram: anchor.web3.SystemProgram.programId,
+        } as any)
+        .rpc(),
+      'WrongAssetType',
+    );
+  });
+
+  it('Accepts a SOL deposit into the escrow PDA', async () => {
+    const before = await provider.connection.getBalance(escrow);
+    await depositSol(cost);
+    const after = await provider.connection.getBalance(escrow);
+    assert.equal(after - before, cost);
+
+    const escrowData = await program.account.escrowAccount.fetch(escrow);
+    assert.equal(escrowData.totalHeld.toNumber(), cost);
+  });
+
+  it('Pays the escrowed SOL out to the owner', async () => {
+    await sleepUntil(due);
+    const before = await provider.connection.getBalance(owner.publicKey);
+    await program.methods
+      .executePayoutSol()
+      .accounts({
+        group,
+        escrow,
+        payoutRecipient: owner.publicKey,
+        feeRecipient,
+        settler: null,
+      } as any)
+      .rpc();
+    const after = await provider.connection.getBalance(owner.publicKey);
+    // The owner also pays the transaction fee
+    assert.isAbove(after - before, cost - 10_000);
+
+    const groupData = await program.account.groupAccount.fetch(group);
+    assert.equal(groupData.isActive, false);
+  });
+
+  it('Returns SOL from an expired group to its members', async () => {
+    const expiredDue = fromNow(10);
+    const expired = await createSolGroup('Expired SOL Group', expiredDue);
+    const members = [
+      anchor.web3.Keypair.generate(),
+      anchor.web3.Keypair.generate(),
+    ];
+    for (const m of members) {
+      await airdrop(m.publicKey);
+      await invite(expired as GroupFixture, m);
+    }
+    await program.methods
+      .depositSol(new anchor.BN(cost / 2))
+      .accounts({
+        ...expired,
+        member: memberPda(expired as GroupFixture, members[0]),
+        memberAuthority: members[0].publicKey,
+        systemProgram: anchor.web3.SystemProgram.programId,
+      } as any)
+      .signers([members[0]])
+      .rpc();
+
+    // Anyone may send the refund; it goes to the member's wallet
+    const claim = () =>
+      program.methods
+        .claimExpiredRefundSol()
+        .accounts({
+          ...expired,
+          member: memberPda(expired as GroupFixture, members[0]),
+          memberAuthority: members[0].publicKey,
+        } as any)
+        .rpc();
+    await expectError(claim(), 'TooEarly');
+    await sleepUntil(expiredDue);
+
+    const before = await provider.connection.getBalance(members[0].publicKey);
+    await claim();
+    const after = await provider.connection.getBalance(members[0].publicKey);
+    assert.equal(after - before, cost / 2);
+    const escrowData = await program.account.escrowAccount.fetch(
+      expired.escrow,
+    );
+    assert.equal(escrowData.totalHeld.toNumber(), 0);
+    await expectError(claim(), 'NothingToRefund');
+  });
+
+  it('Rejects the SOL path on a token group', async () => {
+    const fixture = await setupGroup({ totalCost: cost });
+    const tokenMember = await addMember(fixture);
+    await expectError(
+      program.methods
+        .depositSol(new anchor.BN(cost))
+        .accounts({
+          group: fixture.group,
+          member: tokenMember.account,
+          memberAuthority: tokenMember.authority.publicKey,
+          escrow: fixture.escrow,
+          systemProgram: anchor.web3.SystemProgram.programId,
+        } as any)
+        .signers([tokenMember.authority])
+        .rpc(),
+      'WrongAssetType',
+    );
+  });
+
+  it('Rejects the token path on a native group', async () => {
+    const fixture = await setupGroup();
+    const tokenMember = await addMember(fixture);
+    await expectError(
+      deposit(
+        { ...fixture, group, escrow },
+        {
+          ...tokenMember,
+          account: memberPda({ group } as GroupFixture, member),
+        },
+        cost,
+      ),
+      'WrongMint',
+    );
+  });
+});
+
+describe('grace period', () => {
+  const grace = 6;
+  let fixture: GroupFixture;
+  let members: MemberFixture[];
+
+  before(async () => {
+    fixture = await setupGroup({
+      totalCost: 300,
+      due: fromNow(6),
+      grace,
+      allowPartial: true,
+    });
+    members = [];
+    for (let i = 0; i < 3; i++) members.push(await addMember(fixture));
+  });
+
+  it('Records an on-time deposit as not late', async () => {
+    await deposit(fixture, members[0], 100);
+    const data = await program.account.memberAccount.fetch(members[0].account);
+    assert.equal(data.hasPaid, true);
+    assert.equal(data.paidLate, false);
+  });
+
+  it('Accepts a deposit within the grace period and flags it late', async () => {
+    await sleepUntil(fixture.due);
+    await deposit(fixture, members[1], 100);
+    const data = await program.account.memberAccount.fetch(members[1].account);
+    assert.equal(data.hasPaid, true);
+    assert.equal(data.paidLate, true);
+  });
+
+  it('Blocks payout until the grace period ends', async () => {
+    await expectError(payout(fixture), 'TooEarly');
+  });
+
+  it('Rejects a deposit after the grace period', async () => {
+    await sleepUntil(fixture.due.addn(grace));
+    await expectError(deposit(fixture, members[2], 100), 'DepositWindowClosed');
+
+    await payout(fixture);
+    assert.equal(await tokenBalance(fixture.ownerTokenAccount), 200);
+  });
+});
+
+describe('deposit deadline', () => {
+  it('Rejects a deadline after the due time', async () => {
+    const due = fromNow(20);
+    await expectError(
+      setupGroup({ due, depositDeadline: due.addn(1) }),
+      'InvalidDepositDeadline',
+    );
+  });
+
+  it('Stops deposits after the deadline, ahead of the due time', async () => {
+    const depositDeadline = fromNow(5);
+    const fixture = await setupGroup({
+      totalCost: 200,
+      due: fromNow(60),
+      depositDeadline,
+    });
+    const [early, late] = [await addMember(fixture), await addMember(fixture)];
+
+    await deposit(fixture, early, 100);
+    await sleepUntil(depositDeadline);
+    await expectError(deposit(fixture, late, 100), 'DepositDeadlinePassed');
+
+    const group = await program.account.groupAccount.fetch(fixture.group);
+    assert.ok(group.depositDeadline.eq(depositDeadline));
+    assert.equal(group.paidCount, 1);
+  });
+});
+
+describe('late fee', () => {
+  let fixture: GroupFixture;
+  let members: MemberFixture[];
+
+  before(async () => {
+    fixture = await setupGroup({
+      totalCost: 200,
+      due: fromNow(6),
+      grace: 6,
+      lateFeeBps: 1000, // 10%
+    });
+    members = [await addMember(fixture), await addMember(fixture)];
+  });
+
+  it('Charges no surcharge on time', async () => {
+    await expectError(deposit(fixture, members[0], 110), 'ExceedsShare');
+    await deposit(fixture, members[0], 100);
+
+    const data = await program.account.memberAccount.fetch(members[0].account);
+    assert.equal(data.hasPaid, true);
+    assert.equal(data.lateFeePaid.toNumber(), 0);
+  });
+
+  it('Requires the surcharge on top of a late share', async () => {
+    await sleepUntil(fixture.due);
+    await expectError(deposit(fixture, members[1], 100), 'LateFeeRequired');
+    await deposit(fixture, members[1], 110);
+
+    const data = await program.account.memberAccount.fetch(members[1].account);
+    assert.equal(data.hasPaid, true);
+    assert.equal(data.paidLate, true);
+    assert.equal(data.contributed.toNumber(), 100);
+    assert.equal(data.lateFeePaid.toNumber(), 10);
+  });
+
+  it('Pays the surcharge out with the rest', async () => {
+    await sleepUntil(fixture.due.addn(6));
+    await payout(fixture);
+    assert.equal(await tokenBalance(fixture.ownerTokenAccount), 210);
+  });
+});
+
+describe('all paid gate', () => {
+  let fixture: GroupFixture;
+  let members: MemberFixture[];
+
+  before(async () => {
+    fixture = await setupGroup({ totalCost: 400, due: fromNow(5) });
+    members = [await addMember(fixture), await addMember(fixture)];
+  });
+
+  it('Rejects payout while a member is unpaid', async () => {
+    await deposit(fixture, members[0], 200);
+    // A partial installment doesn't count as paid
+    await deposit(fixture, members[1], 100);
+
+    const group = await program.account.groupAccount.fetch(fixture.group);
+    assert.equal(group.paidCount, 1);
+
+    await sleepUntil(fixture.due);
+    await expectError(payout(fixture), 'NotFullyFunded');
+  });
+});
+
+describe('all paid gate, fully funded', () => {
+  it('Pays out once every member has paid', async () => {
+    const fixture = await setupGroup({ totalCost: 400, due: fromNow(5) });
+    const members = [await addMember(fixture), await addMember(fixture)];
+    for (const m of members) await deposit(fixture, m, 200);
+
+    await sleepUntil(fixture.due);
+    await payout(fixture);
+    assert.equal(await tokenBalance(fixture.ownerTokenAccount), 400);
+  });
+});
+
+describe('empty payout', () => {
+  it('Closes a cycle nobody joined without moving tokens', async () => {
+    const fixture = await setupGroup({ due: fromNow(4), minMembers: 0 });
+    await sleepUntil(fixture.due);
+    await payout(fixture);
+
+    const group = await program.account.groupAccount.fetch(fixture.group);
+    assert.equal(group.isActive, false);
+    assert.equal(await tokenBalance(fixture.ownerTokenAccount), 0);
+  });
+
+  it('Rejects it when the group opts out of empty payouts', async () => {
+    const fixture = await setupGroup({
+      due: fromNow(4),
+      minMembers: 0,
+      rejectEmptyPayout: true,
+    });
+    await sleepUntil(fixture.due);
+    await expectError(payout(fixture), 'NothingToPayOut');
+
+    const group = await program.account.groupAccount.fetch(fixture.group);
+    assert.equal(group.isActive, true);
+  });
+});
+
+describe('payout fee', () => {
+  it('Splits the escrow between the fee recipient and the owner', async () => {
+    // 2.5% of 999 is 24.975, rounded down to 24
+    const fixture = await setupGroup({
+      totalCost: 999,
+      due: fromNow(5),
+      feeBps: 250,
+    });
+    const member = await addMember(fixture);
+    await deposit(fixture, member, 999);
+
+    await sleepUntil(fixture.due);
+    const events = await eventsOf(await payout(fixture));
+    const fee = await tokenBalance(fixture.feeTokenAccount);
+    const toOwner = await tokenBalance(fixture.ownerTokenAccount);
+    assert.equal(fee, 24);
+    assert.equal(toOwner, 975);
+    assert.equal(fee + toOwner, 999);
+    assert.equal(events[0].data.fee.toNumber(), 24);
+    assert.equal(events[0].data.amount.toNumber(), 975);
+  });
+});
+
+describe('invite only', () => {
+  it('Lets any wallet join an open group', async () => {
+    const fixture = await setupGroup();
+    const wallet = anchor.web3.Keypair.generate();
+    await airdrop(wallet.publicKey);
+    await invite(fixture, wallet);
+
+    const group = await program.account.groupAccount.fetch(fixture.group);
+    assert.equal(group.inviteOnly, false);
+    assert.equal(group.memberCount, 1);
+  });
+
+  it('Requires the owner to sign invites to a private group', async () => {
+    const fixture = await setupGroup({ inviteOnly: true });
+    const wallet = anchor.web3.Keypair.generate();
+    await airdrop(wallet.publicKey);
+
+    await expectError(invite(fixture, wallet), 'Unauthorized');
+    const stranger = anchor.web3.Keypair.generate();
+    await expectError(
+      program.methods
+        .inviteMember(1, null)
+        .accounts({
+          group: fixture.group,
+          member: memberPda(fixture, wallet),
+          memberAuthority: wallet.publicKey,
+          owner: stranger.publicKey,
+          payer: wallet.publicKey,
+          systemProgram: anchor.web3.SystemProgram.programId,
+        } as any)
+        .signers([wallet, stranger])
+        .rpc(),
+      'Unauthorized',
+    );
+
+    await invite(fixture, wallet, 1, owner.publicKey);
+    const group = await program.account.groupAccount.fetch(fixture.group);
+    assert.equal(group.memberCount, 1);
+  });
+});
+
+describe('sponsored invites', () => {
+  // Invites `wallet` without its signature, with `payer` covering the rent
+  const sponsoredInvite = (
+    fixture: GroupFixture,
+    wallet: anchor.web3.PublicKey,
+    payer: anchor.web3.Keypair,
+    inviter: anchor.web3.PublicKey | null,
+  ) =>
+    program.methods
+      .inviteMember(1, null)
+      .accounts({
+        group: fixture.group,
+        member: anchor.web3.PublicKey.findProgramAddressSync(
+          [fixture.group.toBuffer(), wallet.toBuffer()],
+          program.programId,
+        )[0],
+        memberAuthority: wallet,
+        owner: inviter,
+        payer: payer.publicKey,
+        systemProgram: anchor.web3.SystemProgram.programId,
+      } as any)
+      .signers([payer])
+      .rpc();
+
+  it('Lets the owner pay rent for an unfunded invitee', async () => {
+    const fixture = await setupGroup();
+    const wallet = anchor.web3.Keypair.generate();
+
+    const before = await provider.connection.getBalance(owner.publicKey);
+    await sponsoredInvite(
+      fixture,
+      wallet.publicKey,
+      owner.payer,
+      owner.publicKey,
+    );
+    const after = await provider.connection.getBalance(owner.publicKey);
+
+    const member = memberPda(fixture, wallet);
+    const rent = await provider.connection.getBalance(member);
+    assert.equal(before - after, rent + 5_000);
+    assert.equal(await provider.connection.getBalance(wallet.publicKey), 0);
+    const data = await program.account.memberAccount.fetch(member);
+    assert.ok(data.member.equals(wallet.publicKey));
+  });
+
+  it('Rejects anyone else signing a wallet up', async () => {
+    const fixture = await setupGroup();
+    const stranger = anchor.web3.Keypair.generate();
+    await airdrop(stranger.publicKey);
+
+    await expectError(
+      sponsoredInvite(
+        fixture,
+        anchor.web3.Keypair.generate().publicKey,
+        stranger,
+        null,
+      ),
+      'Unauthorized',
+    );
+  });
+});
+
+describe('batch_invite', () => {
+  const batchInvite = (
+    fixture: GroupFixture,
+    wallets: anchor.web3.Keypair[],
+  ) =>
+    program.methods
+      .batchInvite(wallets.map((w) => w.publicKey))
+      .accounts({
+        group: fixture.group,
+        owner: owner.publicKey,
+        systemProgram: anchor.web3.SystemProgram.programId,
+      } as any)
+      .remainingAccounts(
+        wallets.map((w) => ({
+          pubkey: memberPda(fixture, w),
+          isWritable: true,
+          isSigner: false,
+        })),
+      )
+      .rpc();
+
+  it('Invites five members in one call', async () => {
+    const fixture = await setupGroup();
+    const wallets = [0, 1, 2, 3, 4].map(() => anchor.web3.Keypair.generate());
+    await batchInvite(fixture, wallets);
+
+    const group = await program.account.groupAccount.fetch(fixture.group);
+    assert.equal(group.memberCount, 5);
+    for (const w of wallets) {
+      const data = await program.account.memberAccount.fetch(
+        memberPda(fixture, w),
+      );
+      assert.ok(data.member.equals(w.publicKey));
+      assert.equal(data.weight, 1);
+      assert.equal(data.hasPaid, false);
+    }
+  });
+
+  it('Rejects a wallet listed twice or already a member', async () => {
+    const fixture = await setupGroup();
+    const wallet = anchor.web3.Keypair.generate();
+    await expectError(batchInvite(fixture, [wallet, wallet]), 'AlreadyMember');
+
+    await batchInvite(fixture, [wallet]);
+    await expectError(batchInvite(fixture, [wallet]), 'AlreadyMember');
+  });
+
+  it('Invites a member whose account was already sent lamports', async () => {
+    const fixture = await setupGroup();
+    const [funded, other] = [0, 1].map(() => anchor.web3.Keypair.generate());
+    await provider.sendAndConfirm(
+      new anchor.web3.Transaction().add(
+        anchor.web3.SystemProgram.transfer({
+          fromPubkey: owner.publicKey,
+          toPubkey: memberPda(fixture, funded),
+          lamports: 1000,
+        }),
+      ),
+    );
+    await batchInvite(fixture, [funded, other]);
+
+    const group = await program.account.groupAccount.fetch(fixture.group);
+    assert.equal(group.memberCount, 2);
+    const account = memberPda(fixture, funded);
+    const data = await program.account.memberAccount.fetch(account);
+    assert.ok(data.member.equals(funded.publicKey));
+    const info = await provider.connection.getAccountInfo(account);
+    assert.ok(info.owner.equals(program.programId));
+  });
+});
+
+describe('remaining account ordering', () => {
+  const asRemaining = (pubkeys: anchor.web3.PublicKey[]) =>
+    pubkeys.map((pubkey) => ({ pubkey, isWritable: true, isSigner: false }));
+
+  it('Rejects batch invite accounts out of order', async () => {
+    const fixture = await setupGroup();
+    const [a, b] = [0, 1].map(() => anchor.web3.Keypair.generate());
+    await expectError(
+      program.methods
+        .batchInvite([a.publicKey, b.publicKey])
+        .accounts({
+          group: fixture.group,
+          owner: owner.publicKey,
+          systemProgram: anchor.web3.SystemProgram.programId,
+        } as any)
+        .remainingAccounts(
+          asRemaining([memberPda(fixture, b), memberPda(fixture, a)]),
+        )
+        .rpc(),
+      'AccountOrderMismatch',
+    );
+    const group = await program.account.groupAccount.fetch(fixture.group);
+    assert.equal(group.memberCount, 0);
+  });
+
+  it('Rejects refund pairs out of order', async () => {
+    const fixture = await setupGroup({ totalCost: 200 });
+    const members = [await addMember(fixture), await addMember(fixture)];
+    for (const m of members) await deposit(fixture, m, 100);
+    await program.methods
+      .cancelGroup()
+      .accounts({ group: fixture.group, owner: owner.publicKey } as any)
+      .rpc();
+
+    const refundAll = (pubkeys: anchor.web3.PublicKey[]) =>
+      program.methods
+        .refundAll()
+        .accounts({
+          group: fixture.group,
+          escrow: fixture.escrow,
+          mint: fixture.mint,
+          escrowTokenAccount: fixture.vault,
+          owner: owner.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        } as any)
+        .remainingAccounts(asRemaining(pubkeys))
+        .rpc();
+
+    // Token accounts swapped between the two members
+    await expectError(
+      refundAll([
+        members[0].account,
+        members[1].tokenAccount,
+        members[1].account,
+        members[0].tokenAccount,
+      ]),
+      'AccountOrderMismatch',
+    );
+    // A token account ahead of its member account
+    await expectError(
+      refundAll([members[0].tokenAccount, members[0].account]),
+      'AccountOrderMismatch',
+    );
+    assert.equal(await tokenBalance(fixture.vault), 200);
+  });
+});
+
+describe('member count bounds', () => {
+  it('Rejects an invite once every seat is taken', async () => {
+    const fixture = await setupGroup({ maxMembers: 2 });
+    await addMember(fixture);
+    await addMember(fixture);
+    await expectError(addMember(fixture), 'GroupFull');
+
+    const group = await program.account.groupAccount.fetch(fixture.group);
+    assert.equal(group.memberCount, 2);
+  });
+
+  it('Rejects a member who would be left without a share', async () => {
+    const fixture = await setupGroup({ totalCost: 3 });
+    await addMember(fixture, 1000, 2);
+    const member = await addMember(fixture);
+    await expectError(addMember(fixture), 'ShareTooSmall');
+
+    // Every member can still pay their share in full
+    await deposit(fixture, member, 1);
+    const data = await program.account.memberAccount.fetch(member.account);
+    assert.equal(data.hasPaid, true);
+  });
+
+  it('Blocks payout until the minimum member count is reached', async () => {
+    const fixture = await setupGroup({
+      totalCost: 400,
+      due: fromNow(5),
+      minMembers: 3,
+      allowPartial: true,
+    });
+    const members = [await addMember(fixture), await addMember(fixture)];
+    for (const m of members) await deposit(fixture, m, 200);
+
+    await sleepUntil(fixture.due);
+    await expectError(payout(fixture), 'TooFewMembers');
+  });
+});
+
+describe('emergency_withdraw', () => {
+  let fixture: GroupFixture;
+
+  const withdraw = (signer = owner.payer) =>
+    program.methods
+      .emergencyWithdraw()
+      .accounts({
+        group: fixture.group,
+        escrow: fixture.escrow,
+        mint: fixture.mint,
+        escrowTokenAccount: fixture.vault,
+        ownerTokenAccount: fixture.ownerTokenAccount,
+        feeRecipientTokenAccount: fixture.feeTokenAccount,
+        owner: signer.publicKey,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      } as any)
+      .signers([signer])
+      .rpc();
+
+  before(async () => {
+    fixture = await setupGroup({ totalCost: 400, due: fromNow(5) });
+    const members = [await addMember(fixture), await addMember(fixture)];
+    await deposit(fixture, members[0], 200);
+  });
+
+  it('Rejects a sweep before the group is past due', async () => {
+    await expectError(withdraw(), 'TooEarly');
+  });
+
+  it('Rejects a sweep until the emergency delay has passed', async () => {
+    await sleepUntil(fixture.due);
+    await expectError(payout(fixture), 'NotFullyFunded');
+    await expectError(withdraw(), 'TooEarly');
+  });
+
+  it('Rejects a sweep by anyone but the owner', async () => {
+    const stranger = anchor.web3.Keypair.generate();
+    await expectError(withdraw(stranger), 'ConstraintHasOne');
+  });
+});
+
+describe('update_cost', () => {
+  const updateCost = (fixture: GroupFixture, newCost: number) =>
+    program.methods
+      .updateCost(new anchor.BN(newCost))
+      .accounts({
+        group: fixture.group,
+        escrow: fixture.escrow,
+        owner: owner.publicKey,
+      } as any)
+      .rpc();
+
+  it('Reprices the group before any deposit', async () => {
+    const fixture = await setupGroup({ totalCost: 200 });
+    const members = [await addMember(fixture), await addMember(fixture)];
+    await updateCost(fixture, 300);
+
+    const group = await program.account.groupAccount.fetch(fixture.group);
+    assert.equal(group.totalCost.toNumber(), 300);
+    await expectError(deposit(fixture, members[0], 151), 'ExceedsShare');
+    await deposit(fixture, members[0], 150);
+  });
+
+  it('Rejects a new cost once a member has deposited', async () => {
+    const fixture = await setupGroup({ totalCost: 200 });
+    const member = await addMember(fixture);
+    await deposit(fixture, member, 50);
+
+    await expectError(updateCost(fixture, 300), 'DepositsMade');
+    const group = await program.account.groupAccount.fetch(fixture.group);
+    assert.equal(group.totalCost.toNumber(), 200);
+  });
+
+  it('Drops an earlier surcharge when repricing', async () => {
+    const fixture = await setupGroup({ totalCost: 200 });
+    const members = [await addMember(fixture), await addMember(fixture)];
+    await program.methods
+      .addSurcharge(new anchor.BN(50))
+      .accounts({ group: fixture.group, owner: owner.publicKey } as any)
+      .remainingAccounts(
+        members.map((m) => ({
+          pubkey: m.account,
+          isWritable: true,
+          isSigner: false,
+        }))
+      )
+      .rpc();
+
+    // Nobody had deposited, so no share was pinned against the surcharge
+    for (const m of members) {
+      const data = await program.account.memberAccount.fetch(m.account);
+      assert.equal(data.owed.toNumber(), 0);
+    }
+    await updateCost(fixture, 300);
+
+    const group = await program.account.groupAccount.fetch(fixture.group);
+    assert.equal(group.totalCost.toNumber(), 300);
+    assert.equal(group.surcharge.toNumber(), 0);
+    await expectError(deposit(fixture, members[0], 151), 'ExceedsShare');
+    await deposit(fixture, members[0], 150);
+    await deposit(fixture, members[1], 150);
+  });
+});
+
+describe('add_surcharge', () => {
+  it('Raises every share and reopens paid members', async () => {
+    const fixture = await setupGroup({ totalCost: 200, due: fromNow(8) });
+    const members = [await addMember(fixture), await addMember(fixture)];
+    for (const m of members) await deposit(fixture, m, 100);
+    const accounts = members.map((m) => ({
+      pubkey: m.account,
+      isWritable: true,
+      isSigner: false,
+    }));
+
+    const surcharge = (remaining: typeof accounts) =>
+      program.methods
+        .addSurcharge(new anchor.BN(50))
+        .accounts({ group: fixture.group, owner: owner.publicKey } as any)
+        .remainingAccounts(remaining)
+        .rpc();
+    await expectError(surcharge(accounts.slice(1)), 'WrongMemberList');
+    const events = await eventsOf(await surcharge(accounts));
+    assert.equal(events[0].name, 'surchargeAdded');
+    assert.equal(events[0].data.totalCost.toNumber(), 250);
+
+    let group = await program.account.groupAccount.fetch(fixture.group);
+    assert.equal(group.totalCost.toNumber(), 250);
+    assert.equal(group.paidCount, 0);
+    for (const m of members) {
+      const data = await program.account.memberAccount.fetch(m.account);
+      assert.equal(data.hasPaid, false);
+      assert.equal(data.owed.toNumber(), 125);
+    }
+
+    for (const m of members) await deposit(fixture, m, 25);
+    group = await program.account.groupAccount.fetch(fixture.group);
+    assert.equal(group.paidCount, 2);
+
+    // The surcharge only applies to the cycle it was added in
+    await sleepUntil(fixture.due);
+    await payout(fixture);
+    assert.equal(await tokenBalance(fixture.ownerTokenAccount), 250);
+    await program.methods
+      .renewGroup(fromNow(60))
+      .accounts({ group: fixture.group, owner: owner.publicKey } as any)
+      .remainingAccounts(accounts)
+      .rpc();
+    group = await program.account.groupAccount.fetch(fixture.group);
+    assert.equal(group.totalCost.toNumber(), 200);
+  });
+});
+
+describe('sweep_empty_group', () => {
+  const sweep = (fixture: GroupFixture, caller: anchor.web3.Keypair) =>
+    program.methods
+      .sweepEmptyGroup()
+      .accounts({
+        group: fixture.group,
+        escrow: fixture.escrow,
+        vault: fixture.vault,
+        owner: owner.publicKey,
+        caller: caller.publicKey,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      } as any)
+      .signers([caller])
+      .rpc();
+
+  it('Lets anyone close a group left without members', async () => {
+    const fixture = await setupGroup({ due: fromNow(5) });
+    const caller = anchor.web3.Keypair.generate();
+    await airdrop(caller.publicKey);
+    await expectError(sweep(fixture, caller), 'TooEarly');
+
+    await sleepUntil(fixture.due);
+    const vaultRent = await provider.connection.getBalance(fixture.vault);
+    const before = await provider.connection.getBalance(owner.publicKey);
+    await sweep(fixture, caller);
+
+    assert.isNull(
+      await program.account.groupAccount.fetchNullable(fixture.group),
+    );
+    assert.isNull(
+      await program.account.escrowAccount.fetchNullable(fixture.escrow),
+    );
+    assert.isNull(await provider.connection.getAccountInfo(fixture.vault));
+    const after = await provider.connection.getBalance(owner.publicKey);
+    // The caller pays the fee, so the owner gets all of the rent back
+    assert.isAbove(after - before, vaultRent);
+  });
+
+  it('Rejects sweeping a group with members', async () => {
+    const fixture = await setupGroup({ due: fromNow(5) });
+    await addMember(fixture);
+    const caller = anchor.web3.Keypair.generate();
+    await airdrop(caller.publicKey);
+
+    await sleepUntil(fixture.due);
+    await expectError(sweep(fixture, caller), 'GroupNotEmpty');
+  });
+});
+
+describe('update_name', () => {
+  const rename = (fixture: GroupFixture, newName: string) =>
+    program.methods
+      .updateName(newName)
+      .accounts({
+        group: fixture.group,
+        owner: owner.publicKey,
+        systemProgram: anchor.web3.SystemProgram.programId,
+      } as any)
+      .rpc();
+
+  it('Shrinks the account to a shorter name and refunds rent', async () => {
+    const longName = `Long ${'n'.repeat(50)} ${++groupCounter}`;
+    const fixture = await setupGroup({ name: longName });
+    const account = await provider.connection.getAccountInfo(fixture.group);
+    const before = await provider.connection.getBalance(owner.publicKey);
+
+    await rename(fixture, 'Short');
+
+    const shrunk = await provider.connection.getAccountInfo(fixture.group);
+    assert.equal(
+      account.data.length - shrunk.data.length,
+      longName.length - 'Short'.length,
+    );
+    assert.isBelow(shrunk.lamports, account.lamports);
+    const refunded = account.lamports - shrunk.lamports;
+    const after = await provider.connection.getBalance(owner.publicKey);
+    // The owner also pays the transaction fee
+    assert.equal(after - before, refunded - 5_000);
+
+    const group = await program.account.groupAccount.fetch(fixture.group);
+    assert.equal(group.groupName, 'Short');
+  });
+
+  it('Rejects a name over 64 bytes', async () => {
+    const fixture = await setupGroup();
+    await expectError(rename(fixture, 'x'.repeat(65)), 'NameTooLong');
+  });
+});
+
+describe('extend_due', () => {
+  let fixture: GroupFixture;
+
+  const extend = (newDue: anchor.BN) =>
+    program.methods
+      .extendDue(newDue)
+      .accounts({ group: fixture.group, owner: owner.publicKey } as any)
+      .rpc();
+
+  before(async () => {
+    fixture = await setupGroup({ due: fromNow(60) });
+  });
+
+  it('Moves the due time later', async () => {
+    const newDue = fixture.due.addn(3600);
+    await extend(newDue);
+    const group = await program.account.groupAccount.fetch(fixture.group);
+    assert.ok(group.subscriptionDue.eq(newDue));
+  });
+
+  it('Rejects moving the due time backwards', async () => {
+    await expectError(extend(fixture.due), 'DueNotLater');
+  });
+
+  it('Rejects an extension of more than 90 days', async () => {
+    const group = await program.account.groupAccount.fetch(fixture.group);
+    const tooFar = group.subscriptionDue.addn(90 * 24 * 60 * 60 + 1);
+    await expectError(extend(tooFar), 'DueTooFar');
+  });
+});
+
+describe('member deadlines', () => {
+  it('Lets a member with a later deadline deposit after the due', async () => {
+    const fixture = await setupGroup({ totalCost: 200, due: fromNow(5) });
+    const member = await addMember(fixture);
+    const lateJoiner = anchor.web3.Keypair.generate();
+    await airdrop(lateJoiner.publicKey);
+    const memberDue = fixture.due.addn(3600);
+
+    // Only the owner can hand out a personal deadline
+    await expectError(
+      invite(fixture, lateJoiner, 1, null, memberDue),
+      'Unauthorized',
+    );
+    await invite(fixture, lateJoiner, 1, owner.publicKey, memberDue);
+    const data = await program.account.memberAccount.fetch(
+      memberPda(fixture, lateJoiner),
+    );
+    assert.ok(data.memberDue.eq(memberDue));
+
+    const tokenAccount = await createAccount(
+      provider.connection,
+      owner.payer,
+      fixture.mint,
+      lateJoiner.publicKey,
+    );
+    await mintTo(
+      provider.connection,
+      owner.payer,
+      fixture.mint,
+      tokenAccount,
+      owner.publicKey,
+      100,
+    );
+    const joiner = {
+      authority: lateJoiner,
+      account: memberPda(fixture, lateJoiner),
+      tokenAccount,
+    };
+
+    await sleepUntil(fixture.due.addn(1));
+    await expectError(deposit(fixture, member, 100), 'DepositWindowClosed');
+    await deposit(fixture, joiner, 100);
+    const paid = await program.account.memberAccount.fetch(joiner.account);
+    assert.equal(paid.hasPaid, true);
+    assert.equal(paid.paidLate, false);
+  });
+});
+
+describe('member list', () => {
+  it('Lists every invited member on the group', async () => {
+    const fixture = await setupGroup();
+    const members = [];
+    for (let i = 0; i < 3; i++) members.push(await addMember(fixture));
+
+    const group = await program.account.groupAccount.fetch(fixture.group);
+    assert.equal(group.members.length, 3);
+    members.forEach((m, i) =>
+      assert.ok(group.members[i].equals(m.authority.publicKey)),
+    );
+  });
+
+  it('Rejects an invite past the list cap', async () => {
+    const fixture = await setupGroup();
+    for (let i = 0; i < 32; i++) await addMember(fixture);
+    await expectError(addMember(fixture), 'MemberListFull');
+  });
+
+  it('Clears the list when the group is cancelled', async () => {
+    const fixture = await setupGroup();
+    await addMember(fixture);
+    await program.methods
+      .cancelGroup()
+      .accounts({ group: fixture.group, owner: owner.publicKey } as any)
+      .rpc();
+
+    const group = await program.account.groupAccount.fetch(fixture.group);
+    assert.equal(group.members.length, 0);
+  });
+});
+
+describe('disputes', () => {
+  const raise = (fixture: GroupFixture, member: MemberFixture) =>
+    program.methods
+      .raiseDispute()
+      .accounts({
+        group: fixture.group,
+        member: member.account,
+        memberAuthority: member.authority.publicKey,
+      } as any)
+      .signers([member.authority])
+      .rpc();
+
+  const resolve = (fixture: GroupFixture, release: boolean) =>
+    program.methods
+      .resolveDispute(release)
+      .accounts({ group: fixture.group, owner: owner.publicKey } as any)
+      .rpc();
+
+  it('Freezes the payout until the owner releases it', async () => {
+    const fixture = await setupGroup({ totalCost: 400, due: fromNow(5) });
+    const members = [await addMember(fixture), await addMember(fixture)];
+    for (const m of members) await deposit(fixture, m, 200);
+    await raise(fixture, members[0]);
+
+    await sleepUntil(fixture.due);
+    await expectError(payout(fixture), 'Disputed');
+
+    await resolve(fixture, true);
+    await payout(fixture);
+    assert.equal(await tokenBalance(fixture.ownerTokenAccount), 400);
+  });
+
+  it('Cancels the group when the owner upholds the dispute', async () => {
+    const fixture = await setupGroup({ totalCost: 400 });
+    const members = [await addMember(fixture), await addMember(fixture)];
+    await deposit(fixture, members[0], 200);
+    await raise(fixture, members[1]);
+    await resolve(fixture, false);
+
+    const group = await program.account.groupAccount.fetch(fixture.group);
+    assert.equal(group.isCancelled, true);
+    assert.equal(group.disputed, false);
+    await refund(fixture, members[0]);
+    assert.equal(await tokenBalance(members[0].tokenAccount), 1000);
+  });
+
+  it('Rejects a dispute raised by a non-member', async () => {
+    const fixture = await setupGroup();
+    const member = await addMember(fixture);
+    const stranger = anchor.web3.Keypair.generate();
+    await expectError(
+      raise(fixture, { ...member, authority: stranger }),
+      'Unauthorized',
+    );
+  });
+});
+
+describe('withdraw_excess', () => {
+  it('Reclaims exactly the tokens sent straight to the vault', async () => {
+    const fixture = await setupGroup({ totalCost: 400 });
+    const member = await addMember(fixture);
+    await deposit(fixture, member, 200);
+    await mintTo(
+      provider.connection,
+      owner.payer,
+      fixture.mint,
+      fixture.vault,
+      owner.publicKey,
+      50,
+    );
+
+    const withdraw = () =>
+      program.methods
+        .withdrawExcess()
+        .accounts({
+          group: fixture.group,
+          escrow: fixture.escrow,
+          mint: fixture.mint,
+          escrowTokenAccount: fixture.vault,
+          ownerTokenAccount: fixture.ownerTokenAccount,
+          owner: owner.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        } as any)
+        .rpc();
+    await withdraw();
+
+    assert.equal(await tokenBalance(fixture.ownerTokenAccount), 50);
+    assert.equal(await tokenBalance(fixture.vault), 200);
+    const escrow = await program.account.escrowAccount.fetch(fixture.escrow);
+    assert.equal(escrow.totalHeld.toNumber(), 200);
+
+    // Tracked deposits can't be taken this way
+    await expectError(withdraw(), 'NoExcess');
+  });
+});
+
+describe('renewal', () => {
+  let fixture: GroupFixture;
+  let members: MemberFixture[];
+
+  const renew = (nextDue: anchor.BN, accounts = members) =>
+    program.methods
+      .renewGroup(nextDue)
+      .accounts({ group: fixture.group, owner: owner.publicKey } as any)
+      .remainingAccounts(
+        accounts.map((m) => ({
+          pubkey: m.account,
+          isWritable: true,
+          isSigner: false,
+        })),
+      )
+      .rpc();
+
+  before(async () => {
+    fixture = await setupGroup({ totalCost: 400, due: fromNow(5) });
+    members = [await addMember(fixture), await addMember(fixture)];
+  });
+
+  it('Rejects renewal before the cycle is paid out', async () => {
+    await expectError(renew(fromNow(60)), 'NotPaidOut');
+  });
+
+  it('Rejects a renewal that leaves out a member', async () => {
+    for (const m of members) await deposit(fixture, m, 200);
+    await sleepUntil(fixture.due);
+    await payout(fixture);
+
+    await expectError(renew(fromNow(60), [members[0]]), 'WrongMemberList');
+    await expectError(
+      renew(fromNow(60), [members[0], members[0]]),
+      'WrongMemberList',
+    );
+  });
+
+  it('Runs a second cycle in which members pay again', async () => {
+    const nextDue = fromNow(6);
+    await renew(nextDue);
+
+    const group = await program.account.groupAccount.fetch(fixture.group);
+    assert.equal(group.isActive, true);
+    assert.equal(group.paidCount, 0);
+    assert.ok(group.subscriptionDue.eq(nextDue));
+    for (const m of members) {
+      const data = await program.account.memberAccount.fetch(m.account);
+      assert.equal(data.hasPaid, false);
+      assert.equal(data.contributed.toNumber(), 0);
+      assert.equal(data.totalContributedLifetime.toNumber(), 200);
+      assert.equal(data.cyclesPaid, 1);
+    }
+
+    // First-cycle payments no longer count, so each member deposits again
+    for (const m of members) await deposit(fixture, m, 200);
+    await sleepUntil(nextDue);
+    await payout(fixture);
+    const renewed = await program.account.groupAccount.fetch(fixture.group);
+    assert.equal(renewed.cycleCount, 2);
+    assert.equal(await tokenBalance(fixture.ownerTokenAccount), 800);
+  });
+
+  it('Keeps a lifetime tally while per-cycle contributions reset', async () => {
+    for (const m of members) {
+      const data = await program.account.memberAccount.fetch(m.account);
+      assert.equal(data.contributed.toNumber(), 200);
+      assert.equal(data.totalContributedLifetime.toNumber(), 400);
+      assert.equal(data.cyclesPaid, 2);
+    }
+  });
+});
+
+describe('renewal summary', () => {
+  it('Reports which members paid late in the cycle that ended', async () => {
+    const fixture = await setupGroup({
+      totalCost: 200,
+      due: fromNow(6),
+      grace: 6,
+    });
+    const [onTime, late] = [await addMember(fixture), await addMember(fixture)];
+    await deposit(fixture, onTime, 100);
+    await sleepUntil(fixture.due);
+    await deposit(fixture, late, 100);
+    await sleepUntil(fixture.due.addn(6));
+    await payout(fixture);
+
+    const signature = await program.methods
+      .renewGroup(fromNow(60))
+      .accounts({ group: fixture.group, owner: owner.publicKey } as any)
+      .remainingAccounts(
+        [onTime, late].map((m) => ({
+          pubkey: m.account,
+          isWritable: true,
+          isSigner: false,
+        })),
+      )
+      .rpc();
+
+    const [event] = (await eventsOf(signature)).filter(
+      (e) => e.name === 'renewalSummary',
+    );
+    assert.equal(event.data.cycle, 1);
+    const [first, second] = event.data.members;
+    assert.ok(first.member.equals(onTime.authority.publicKey));
+    assert.equal(first.paidLate, false);
+    assert.ok(second.member.equals(late.authority.publicKey));
+    assert.equal(second.paidLate, true);
+    for (const m of event.data.members) {
+      assert.equal(m.paid, true);
+      assert.equal(m.cyclesPaid, 1);
+    }
+
+    // The per-cycle flags are cleared once reported
+    const data = await program.account.memberAccount.fetch(late.account);
+    assert.equal(data.paidLate, false);
+  });
+});
+
+describe('auto-pay', () => {
+  const setAutoPay = (member: MemberFixture, enabled: boolean) =>
+    program.methods
+      .setAutoPay(enabled)
+      .accounts({
+        member: member.account,
+        memberAuthority: member.authority.publicKey,
+      } as any)
+      .signers([member.authority])
+      .rpc();
+
+  it('Toggles the flag and reports it at renewal', async () => {
+    const fixture = await setupGroup({ totalCost: 200, due: fromNow(5) });
+    const [member, other] = [
+      await addMember(fixture),
+      await addMember(fixture),
+    ];
+    await setAutoPay(member, true);
+    let data = await program.account.memberAccount.fetch(member.account);
+    assert.equal(data.autoPay, true);
+    await setAutoPay(member, false);
+    data = await program.account.memberAccount.fetch(member.account);
+    assert.equal(data.autoPay, false);
+
+    await setAutoPay(member, true);
+    for (const m of [member, other]) await deposit(fixture, m, 100);
+    await sleepUntil(fixture.due);
+    await payout(fixture);
+    const signature = await program.methods
+      .renewGroup(fromNow(60))
+      .accounts({ group: fixture.group, owner: owner.publicKey } as any)
+      .remainingAccounts(
+        [member, other].map((m) => ({
+          pubkey: m.account,
+          isWritable: true,
+          isSigner: false,
+        })),
+      )
+      .rpc();
+    const [event] = (await eventsOf(signature)).filter(
+      (e) => e.name === 'renewalSummary',
+    );
+    assert.deepEqual(
+      event.data.members.map((m) => m.autoPay),
+      [true, false],
+    );
+  });
+
+  it("Rejects setting another member's flag", async () => {
+    const fixture = await setupGroup();
+    const member = await addMember(fixture);
+    const stranger = anchor.web3.Keypair.generate();
+    await expectError(
+      setAutoPay({ ...member, authority: stranger }, true),
+      'Unauthorized',
+    );
+  });
+});
+
+describe('first deposit time', () => {
+  it('Records when each member first deposited this cycle', async () => {
+    const fixture = await setupGroup({ totalCost: 200, due: fromNow(8) });
+    const [early, late] = [await addMember(fixture), await addMember(fixture)];
+    const firstDepositAt = async (member: MemberFixture) => {
+      const data = await program.account.memberAccount.fetch(member.account);
+      return data.firstDepositAt.toNumber();
+    };
+
+    assert.equal(await firstDepositAt(early), 0);
+    await deposit(fixture, early, 50);
+    const earlyAt = await firstDepositAt(early);
+    assert.isAbove(earlyAt, 0);
+
+    await sleepUntil(new anchor.BN(earlyAt + 1));
+    await deposit(fixture, late, 100);
+    // Topping up keeps the time of the first deposit
+    await deposit(fixture, early, 50);
+    assert.equal(await firstDepositAt(early), earlyAt);
+    assert.isAbove(await firstDepositAt(late), earlyAt);
+
+    await sleepUntil(fixture.due);
+    await payout(fixture);
+    await program.methods
+      .renewGroup(fromNow(60))
+      .accounts({ group: fixture.group, owner: owner.publicKey } as any)
+      .remainingAccounts(
+        [early, late].map((m) => ({
+          pubkey: m.account,
+          isWritable: true,
+          isSigner: false,
+        })),
+      )
+      .rpc();
+    assert.equal(await firstDepositAt(early), 0);
+    assert.equal(await firstDepositAt(late), 0);
+  });
+});
+
+describe('refund penalty', () => {
+  const withdrawExcess = (fixture: GroupFixture) =>
+    program.methods
+      .withdrawExcess()
+      .accounts({
+        group: fixture.group,
+        escrow: fixture.escrow,
+        mint: fixture.mint,
+        escrowTokenAccount: fixture.vault,
+        ownerTokenAccount: fixture.ownerTokenAccount,
+        owner: owner.publicKey,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      } as any)
+      .rpc();
+
+  it('Rejects a penalty above 100%', async () => {
+    await expectError(
+      setupGroup({ refundPenaltyBps: 10_001 }),
+      'InvalidRefundPenalty',
+    );
+  });
+
+  it('Keeps the penalty in escrow and pays it out', async () => {
+    const fixture = await setupGroup({
+      totalCost: 1000,
+      due: fromNow(8),
+      refundPenaltyBps: 1000, // 10%
+    });
+    const [leaver, stayer] = [
+      await addMember(fixture),
+      await addMember(fixture),
+    ];
+    await deposit(fixture, leaver, 500);
+    await refund(fixture, leaver);
+
+    assert.equal(await tokenBalance(leaver.tokenAccount), 950);
+    assert.equal(await tokenBalance(fixture.vault), 50);
+    let escrow = await program.account.escrowAccount.fetch(fixture.escrow);
+    assert.equal(escrow.totalHeld.toNumber(), 50);
+    assert.equal(escrow.penalties.toNumber(), 50);
+
+    // The penalty sits on top of the cost, so both shares still fit
+    await deposit(fixture, leaver, 500);
+    await deposit(fixture, stayer, 500);
+    await sleepUntil(fixture.due);
+    await payout(fixture);
+    assert.equal(await tokenBalance(fixture.ownerTokenAccount), 1050);
+    escrow = await program.account.escrowAccount.fetch(fixture.escrow);
+    assert.equal(escrow.penalties.toNumber(), 0);
+  });
+
+  it('Returns penalties of a cancelled group to the owner', async () => {
+    const fixture = await setupGroup({ refundPenaltyBps: 2000 });
+    const member = await addMember(fixture);
+    await deposit(fixture, member, 500);
+    await refund(fixture, member);
+    assert.equal(await tokenBalance(member.tokenAccount), 900);
+
+    await program.methods
+      .cancelGroup()
+      .accounts({ group: fixture.group, owner: owner.publicKey } as any)
+      .rpc();
+    await withdrawExcess(fixture);
+    assert.equal(await tokenBalance(fixture.ownerTokenAccount), 100);
+    const escrow = await program.account.escrowAccount.fetch(fixture.escrow);
+    assert.equal(escrow.totalHeld.toNumber(), 0);
+  });
+});
+
+describe('get_outstanding', () => {
+  const outstanding = (fixture: GroupFixture, member: MemberFixture) =>
+    program.methods
+      .getOutstanding()
+      .accounts({ group: fixture.group, member: member.account } as any)
+      .view();
+
+  it('Returns the unpaid remainder of a weighted share', async () => {
+    const fixture = await setupGroup({ totalCost: 900 });
+    const single = await addMember(fixture, 1000, 1);
+    const double = await addMember(fixture, 1000, 2);
+
+    assert.equal((await outstanding(fixture, double)).toNumber(), 600);
+    await deposit(fixture, double, 250);
+    assert.equal((await outstanding(fixture, double)).toNumber(), 350);
+    await deposit(fixture, double, 350);
+    assert.equal((await outstanding(fixture, double)).toNumber(), 0);
+    assert.equal((await outstanding(fixture, single)).toNumber(), 300);
+  });
+
+  it('Spreads rounding dust over the first members invited', async () => {
+    const fixture = await setupGroup({ totalCost: 100 });
+    const members = [];
+    for (let i = 0; i < 3; i++) members.push(await addMember(fixture));
+
+    const shares = [];
+    for (const m of members) {
+      shares.push((await outstanding(fixture, m)).toNumber());
+    }
+    assert.deepEqual(shares, [34, 33, 33]);
+    assert.equal(shares.reduce((a, b) => a + b, 0), 100);
+  });
+});
+
+describe('co-recipients', () => {
+  const wallets = [0, 1, 2].map(
+    () => anchor.web3.Keypair.generate().publicKey,
+  );
+  const bps = [5000, 3000, 2000];
+
+  it('Rejects recipient shares that do not add up to 100%', async () => {
+    await expectError(
+      setupGroup({
+        recipients: wallets.map((pubkey) => ({ pubkey, bps: 3000 })),
+      }),
+      'InvalidRecipientSplit',
+    );
+  });
+
+  it('Rejects more than sixteen recipients', async () => {
+    const many = [...Array(17).keys()].map((i) => ({
+      pubkey: anchor.web3.Keypair.generate().publicKey,
+      bps: i === 0 ? 2000 : 500,
+    }));
+    await expectError(setupGroup({ recipients: many }), 'TooManyRecipients');
+  });
+
+  it('Splits the payout between three recipients', async () => {
+    const fixture = await setupGroup({
+      totalCost: 1000,
+      due: fromNow(5),
+      recipients: wallets.map((pubkey, i) => ({ pubkey, bps: bps[i] })),
+    });
+    const accounts = [];
+    for (const wallet of wallets) {
+      accounts.push(
+        await createAccount(
+          provider.connection,
+          owner.payer,
+          fixture.mint,
+          wallet,
+        ),
+      );
+    }
+    const member = await addMember(fixture);
+    await deposit(fixture, member, 1000);
+
+    await sleepUntil(fixture.due);
+    await expectError(
+      payout(fixture, [accounts[1], accounts[0], accounts[2]]),
+      'AccountOrderMismatch',
+    );
+    await payout(fixture, accounts);
+
+    assert.equal(await tokenBalance(accounts[0]), 500);
+    assert.equal(await tokenBalance(accounts[1]), 300);
+    assert.equal(await tokenBalance(accounts[2]), 200);
+    assert.equal(await tokenBalance(fixture.ownerTokenAccount), 0);
+  });
+});
+
+describe('payout pages', () => {
+  const wallets = [...Array(8).keys()].map(
+    () => anchor.web3.Keypair.generate().publicKey,
+  );
+
+  const payoutPage = (
+    fixture: GroupFixture,
+    start: number,
+    recipientAccounts: anchor.web3.PublicKey[],
+  ) =>
+    program.methods
+      .executePayoutPage(start, recipientAccounts.length)
+      .accounts({
+        group: fixture.group,
+        escrow: fixture.escrow,
+        mint: fixture.mint,
+        escrowTokenAccount: fixture.vault,
+        feeRecipientTokenAccount: fixture.feeTokenAccount,
+        settler: null,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      } as any)
+      .remainingAccounts(
+        recipientAccounts.map((pubkey) => ({
+          pubkey,
+          isWritable: true,
+          isSigner: false,
+        })),
+      )
+      .rpc();
+
+  // Token accounts of the eight recipients, in order
+  const recipientAccounts = async (fixture: GroupFixture) => {
+    const accounts = [];
+    for (const wallet of wallets) {
+      accounts.push(
+        await createAccount(
+          provider.connection,
+          owner.payer,
+          fixture.mint,
+          wallet,
+        ),
+      );
+    }
+    return accounts;
+  };
+
+  it('Pays eight recipients across two pages', async () => {
+    const fixture = await setupGroup({
+      totalCost: 800,
+      due: fromNow(5),
+      recipients: wallets.map((pubkey) => ({ pubkey, bps: 1250 })),
+    });
+    const accounts = await recipientAccounts(fixture);
+    const member = await addMember(fixture);
+    await deposit(fixture, member, 800);
+    await sleepUntil(fixture.due);
+
+    await payoutPage(fixture, 0, accounts.slice(0, 4));
+    let group = await program.account.groupAccount.fetch(fixture.group);
+    assert.equal(group.isActive, true);
+    let escrow = await program.account.escrowAccount.fetch(fixture.escrow);
+    assert.equal(escrow.paidCursor, 4);
+    for (const account of accounts.slice(0, 4)) {
+      assert.equal(await tokenBalance(account), 100);
+    }
+
+    await expectError(
+      payoutPage(fixture, 0, accounts.slice(0, 4)),
+      'PageOutOfOrder',
+    );
+    await expectError(payout(fixture, accounts), 'PayoutInProgress');
+
+    await payoutPage(fixture, 4, accounts.slice(4));
+    group = await program.account.groupAccount.fetch(fixture.group);
+    assert.equal(group.isActive, false);
+    escrow = await program.account.escrowAccount.fetch(fixture.escrow);
+    assert.equal(escrow.paidCursor, 0);
+    for (const account of accounts) {
+      assert.equal(await tokenBalance(account), 100);
+    }
+  });
+
+  it('Holds deposits back until the last page is paid', async () => {
+    const fixture = await setupGroup({
+      totalCost: 800,
+      due: fromNow(8),
+      allowPartial: true,
+      recipients: wallets.map((pubkey) => ({ pubkey, bps: 1250 })),
+    });
+    // A member with a later deadline can still deposit once payouts open
+    const lateJoiner = anchor.web3.Keypair.generate();
+    await airdrop(lateJoiner.publicKey);
+    const memberDue = fixture.due.addn(3600);
+    await invite(fixture, lateJoiner, 1, owner.publicKey, memberDue);
+    const member = await addMember(fixture);
+    await deposit(fixture, member, 400);
+
+    const tokenAccount = await createAccount(
+      provider.connection,
+      owner.payer,
+      fixture.mint,
+      lateJoiner.publicKey,
+    );
+    await mintTo(
+      provider.connection,
+      owner.payer,
+      fixture.mint,
+      tokenAccount,
+      owner.publicKey,
+      400,
+    );
+    const joiner = {
+      authority: lateJoiner,
+      account: memberPda(fixture, lateJoiner),
+      tokenAccount,
+    };
+    const accounts = await recipientAccounts(fixture);
+
+    await sleepUntil(fixture.due);
+    await payoutPage(fixture, 0, accounts.slice(0, 4));
+
+    // Splitting a larger total on the last page would overdraw the vault
+    await expectError(deposit(fixture, joiner, 400), 'PayoutInProgress');
+
+    await payoutPage(fixture, 4, accounts.slice(4));
+    for (const account of accounts) {
+      assert.equal(await tokenBalance(account), 50);
+    }
+    assert.equal(await tokenBalance(fixture.vault), 0);
+    assert.equal(await tokenBalance(joiner.tokenAccount), 400);
+  });
+
+  it('Freezes the group until the last page is paid', async () => {
+    const fixture = await setupGroup({
+      totalCost: 800,
+      due: fromNow(8),
+      allowPartial: true,
+      allowLateJoin: true,
+      recipients: wallets.map((pubkey) => ({ pubkey, bps: 1250 })),
+    });
+    const paid = await addMember(fixture);
+    const unpaid = await addMember(fixture);
+    await deposit(fixture, paid, 400);
+    const accounts = await recipientAccounts(fixture);
+    await sleepUntil(fixture.due);
+    await payoutPage(fixture, 0, accounts.slice(0, 4));
+
+    // Cancelling, directly or by dispute, or refunding would now pay back
+    // tokens that already left the vault
+    await expectError(
+      program.methods
+        .cancelGroup()
+        .accounts({ group: fixture.group, owner: owner.publicKey } as any)
+        .rpc(),
+      'PayoutInProgress',
+    );
+    await expectError(
+      program.methods
+        .raiseDispute()
+        .accounts({
+          group: fixture.group,
+          member: unpaid.account,
+          memberAuthority: unpaid.authority.publicKey,
+        } as any)
+        .signers([unpaid.authority])
+        .rpc(),
+      'PayoutInProgress',
+    );
+    await expectError(
+      program.methods
+        .claimExpiredRefund()
+        .accounts({
+          group: fixture.group,
+          member: paid.account,
+          escrow: fixture.escrow,
+          mint: fixture.mint,
+          memberTokenAccount: paid.tokenAccount,
+          escrowTokenAccount: fixture.vault,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        } as any)
+        .rpc(),
+      'PayoutInProgress',
+    );
+    // A surcharge or a newcomer would leave the last page a paid member short
+    await expectError(
+      program.methods
+        .addSurcharge(new anchor.BN(100))
+        .accounts({ group: fixture.group, owner: owner.publicKey } as any)
+        .remainingAccounts(
+          [paid, unpaid].map((m) => ({
+            pubkey: m.account,
+            isWritable: true,
+            isSigner: false,
+          })),
+        )
+        .rpc(),
+      'PayoutInProgress',
+    );
+    await expectError(addMember(fixture), 'PayoutInProgress');
+
+    await payoutPage(fixture, 4, accounts.slice(4));
+    const group = await program.account.groupAccount.fetch(fixture.group);
+    assert.equal(group.isActive, false);
+    assert.equal(group.payoutInProgress, false);
+    for (const account of accounts) {
+      assert.equal(await tokenBalance(account), 50);
+    }
+  });
+});
+
+describe('return data', () => {
+  it('Returns the outstanding share and the payout amount', async () => {
+    const fixture = await setupGroup({ totalCost: 300, due: fromNow(5) });
+    const member = await addMember(fixture);
+
+    assert.equal(await returnedU64(await deposit(fixture, member, 100)), 200);
+    assert.equal(await returnedU64(await deposit(fixture, member, 200)), 0);
+
+    await sleepUntil(fixture.due);
+    assert.equal(await returnedU64(await payout(fixture)), 300);
+  });
+});
+
+// Only present when the program is built with `--features test-clock`
+const hasTestClock = program.idl.instructions.some(
+  (ix) => ix.name === 'set_test_clock',
+);
+
+(hasTestClock ? describe : describe.skip)('test clock', () => {
+  const [testClock] = anchor.web3.PublicKey.findProgramAddressSync(
+    [Buffer.from('test-clock')],
+    program.programId,
+  );
+
+  const setClock = (unixTimestamp: anchor.BN) =>
+    (program.methods as any)
+      .setTestClock(unixTimestamp)
+      .accounts({
+        testClock,
+        payer: owner.publicKey,
+        systemProgram: anchor.web3.SystemProgram.programId,
+      })
+      .rpc();
+
+  // A week past the grace period, as in the program
+  const EMERGENCY_DELAY = 7 * 24 * 60 * 60;
+
+  const sweep = (fixture: GroupFixture) =>
+    program.methods
+      .emergencyWithdraw()
+      .accounts({
+        group: fixture.group,
+        escrow: fixture.escrow,
+        mint: fixture.mint,
+        escrowTokenAccount: fixture.vault,
+        ownerTokenAccount: fixture.ownerTokenAccount,
+        feeRecipientTokenAccount: fixture.feeTokenAccount,
+        owner: owner.publicKey,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      } as any)
+      .remainingAccounts([
+        { pubkey: testClock, isWritable: false, isSigner: false },
+      ])
+      .rpc();
+
+  let fixture: GroupFixture;
+
+  before(async () => {
+    fixture = await setupGroup({ totalCost: 100, due: fromNow(3600) });
+    const member = await addMember(fixture);
+    await deposit(fixture, member, 100);
+  });
+
+  it('Refuses a payout while the test clock is before due', async () => {
+    await setClock(fixture.due.subn(10));
+    await expectError(payout(fixture, [testClock]), 'TooEarly');
+  });
+
+  it('Pays out once the test clock passes the due time', async () => {
+    await setClock(fixture.due.addn(1));
+    await payout(fixture, [testClock]);
+    const group = await program.account.groupAccount.fetch(fixture.group);
+    assert.equal(group.isActive, false);
+    assert.equal(await tokenBalance(fixture.ownerTokenAccount), 100);
+  });
+
+  it('Refuses refunds once the test clock passes the due time', async () => {
+    const late = await setupGroup({ totalCost: 100, due: fromNow(3600) });
+    const member = await addMember(late);
+    await deposit(late, member, 100);
+    await setClock(late.due.addn(1));
+    await expectError(
+      program.methods
+        .refundMember()
+        .accounts({
+          group: late.group,
+          member: member.account,
+          memberAuthority: member.authority.publicKey,
+          mint: late.mint,
+          memberTokenAccount: member.tokenAccount,
+          escrowTokenAccount: late.vault,
+          escrow: late.escrow,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        } as any)
+        .remainingAccounts([
+          { pubkey: testClock, isWritable: false, isSigner: false },
+        ])
+        .signers([member.authority])
+        .rpc(),
+      'TooLate',
+    );
+  });
+
+  it('Holds a large emergency sweep for approvals', async () => {
+    const approvers = [...Array(2).keys()].map(() =>
+      anchor.web3.Keypair.generate(),
+    );
+    for (const approver of approvers) await airdrop(approver.publicKey);
+    const swept = await setupGroup({
+      totalCost: 1200,
+      due: fromNow(3600),
+      approvers: approvers.map((approver) => approver.publicKey),
+      threshold: 2,
+      approvalAbove: 500,
+    });
+    const members = [await addMember(swept), await addMember(swept)];
+    await deposit(swept, members[0], 600);
+
+    await setClock(swept.due.addn(EMERGENCY_DELAY + 1));
+    await expectError(sweep(swept), 'ApprovalsMissing');
+
+    for (const approver of approvers) {
+      await program.methods
+        .approvePayout()
+        .accounts({
+          group: swept.group,
+          escrow: swept.escrow,
+          approver: approver.publicKey,
+        } as any)
+        .signers([approver])
+        .rpc();
+    }
+    await sweep(swept);
+    assert.equal(await tokenBalance(swept.ownerTokenAccount), 600);
+  });
+
+  it('Leaves a fully funded group to the normal payout', async () => {
+    const funded = await setupGroup({ totalCost: 100, due: fromNow(3600) });
+    await deposit(funded, await addMember(funded), 100);
+
+    await setClock(funded.due.addn(EMERGENCY_DELAY + 1));
+    await expectError(sweep(funded), 'GroupFunded');
+    await payout(funded, [testClock]);
+    assert.equal(await tokenBalance(funded.ownerTokenAccount), 100);
+  });
+});
+
+describe('seconds_until_payout', () => {
+  it('Counts down through the grace period and settlement delay', async () => {
+    const fixture = await setupGroup({
+      totalCost: 100,
+      due: fromNow(6),
+      grace: 2,
+      settlementDelay: 3,
+    });
+    await deposit(fixture, await addMember(fixture), 100);
+    const secondsUntilPayout = async () =>
+      (
+        await program.methods
+          .secondsUntilPayout()
+          .accounts({ group: fixture.group } as any)
+          .view()
+      ).toNumber();
+
+    const before = await secondsUntilPayout();
+    assert.isAbove(before, 5);
+    assert.isAtMost(before, 11);
+
+    // Past the due time the grace period and delay still remain
+    await sleepUntil(fixture.due);
+    const afterDue = await secondsUntilPayout();
+    assert.isAbove(afterDue, 0);
+    assert.isAtMost(afterDue, 5);
+    await expectError(payout(fixture), 'TooEarly');
+
+    await sleepUntil(fixture.due.addn(5));
+    assert.equal(await secondsUntilPayout(), 0);
+    await payout(fixture);
+  });
+});
+
+describe('get_group_status', () => {
+  it('Returns a snapshot of the collection mid-way', async () => {
+    const fixture = await setupGroup({ totalCost: 900, due: fromNow(60) });
+    const members = [];
+    for (let i = 0; i < 3; i++) members.push(await addMember(fixture));
+    await deposit(fixture, members[0], 300);
+    await deposit(fixture, members[1], 100);
+
+    const status = await program.methods
+      .getGroupStatus()
+      .accounts({ group: fixture.group, escrow: fixture.escrow } as any)
+      .view();
+    assert.equal(status.memberCount, 3);
+    assert.equal(status.paidCount, 1);
+    assert.equal(status.totalHeld.toNumber(), 400);
+    assert.equal(status.isActive, true);
+    const remaining = status.secondsUntilDue.toNumber();
+    assert.isAbove(remaining, 0);
+    assert.isAtMost(remaining, 60);
+  });
+});
+
+describe('check_solvency', () => {
+  const checkSolvency = (fixture: GroupFixture) =>
+    program.methods.checkSolvency().accounts({
+      group: fixture.group,
+      escrow: fixture.escrow,
+      escrowTokenAccount: fixture.vault,
+    } as any);
+
+  it('Reports tokens sent straight to the vault as drift', async () => {
+    const fixture = await setupGroup({ totalCost: 300 });
+    await deposit(fixture, await addMember(fixture), 300);
+    assert.equal(await checkSolvency(fixture).view(), true);
+
+    await mintTo(
+      provider.connection,
+      owner.payer,
+      fixture.mint,
+      fixture.vault,
+      owner.publicKey,
+      25,
+    );
+    assert.equal(await checkSolvency(fixture).view(), false);
+    const { events } = await checkSolvency(fixture).simulate();
+    assert.equal(events[0].name, 'solvencyChecked');
+    assert.equal(events[0].data.expected.toNumber(), 300);
+    assert.equal(events[0].data.actual.toNumber(), 325);
+    assert.equal(events[0].data.delta.toNumber(), 25);
+  });
+});
+
+describe('multiple mints', () => {
+  it('Pays out each accepted mint from its own escrow', async () => {
+    const usdc = await setupGroup({ totalCost: 400, due: fromNow(8) });
+    const usdt = await addEscrow(usdc);
+    const first = await addMember(usdc);
+    const second = await addMember(usdt);
+
+    await deposit(usdc, first, 200);
+    await deposit(usdt, second, 200);
+    // A member can't spread their share over a second mint
+    const secondUsdc = await createAccount(
+      provider.connection,
+      owner.payer,
+      usdc.mint,
+      second.authority.publicKey,
+    );
+    await mintTo(
+      provider.connection,
+      owner.payer,
+      usdc.mint,
+      secondUsdc,
+      owner.publicKey,
+      1,
+    );
+    await expectError(
+      deposit(usdc, { ...second, tokenAccount: secondUsdc }, 1),
+      'MixedMints',
+    );
+
+    await sleepUntil(usdc.due);
+    await payout(usdc);
+    let group = await program.account.groupAccount.fetch(usdc.group);
+    assert.equal(group.isActive, true);
+    await expectError(payout(usdc), 'AlreadyPaidOut');
+
+    await payout(usdt);
+    assert.equal(await tokenBalance(usdc.ownerTokenAccount), 200);
+    assert.equal(await tokenBalance(usdt.ownerTokenAccount), 200);
+    group = await program.account.groupAccount.fetch(usdc.group);
+    assert.equal(group.isActive, false);
+    assert.equal(group.cycleCount, 1);
+  });
+});
+
+describe('allowed mints', () => {
+  const newMint = async () =>
+    (await createMintAccounts(anchor.web3.Keypair.generate().publicKey)).mint;
+
+  it('Accepts the listed mints and rejects any other', async () => {
+    const usdt = await newMint();
+    const fixture = await setupGroup({ extraMints: [usdt] });
+    const group = await program.account.groupAccount.fetch(fixture.group);
+    assert.equal(group.allowedMints.length, 2);
+
+    await initEscrow(fixture.group, usdt);
+    await expectError(
+      initEscrow(fixture.group, await newMint()),
+      'MintNotAllowed',
+    );
+  });
+
+  it('Rejects more than four allowed mints', async () => {
+    const extraMints = [];
+    for (let i = 0; i < 4; i++) extraMints.push(await newMint());
+    await expectError(setupGroup({ extraMints }), 'TooManyMints');
+  });
+});
+
+describe('initialize_escrow_if_needed', () => {
+  it('Treats a repeated call as a no-op', async () => {
+    const fixture = await setupGroup({ totalCost: 100 });
+    const init = () =>
+      program.methods
+        .initializeEscrowIfNeeded()
+        .accounts({
+          group: fixture.group,
+          escrow: fixture.escrow,
+          mint: fixture.mint,
+          escrowTokenAccount: fixture.vault,
+          owner: owner.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: anchor.web3.SystemProgram.programId,
+        } as any)
+        .rpc();
+
+    // setupGroup already created the escrow; money in it must survive a retry
+    const member = await addMember(fixture);
+    await deposit(fixture, member, 60);
+    await init();
+    await init();
+
+    const escrow = await program.account.escrowAccount.fetch(fixture.escrow);
+    assert.ok(escrow.group.equals(fixture.group));
+    assert.ok(escrow.vault.equals(fixture.vault));
+    assert.equal(escrow.totalHeld.toNumber(), 60);
+    const group = await program.account.groupAccount.fetch(fixture.group);
+    assert.equal(group.escrowCount, 1);
+  });
+
+  it('Creates a missing escrow once', async () => {
+    const fixture = await setupGroup();
+    const { mint } = await createMintAccounts(fixture.feeRecipient);
+    const [escrow] = anchor.web3.PublicKey.findProgramAddressSync(
+      [fixture.group.toBuffer(), mint.toBuffer()],
+      program.programId,
+    );
+    const [vault] = anchor.web3.PublicKey.findProgramAddressSync(
+      [Buffer.from('vault'), fixture.group.toBuffer(), mint.toBuffer()],
+      program.programId,
+    );
+    for (let i = 0; i < 2; i++) {
+      await program.methods
+        .initializeEscrowIfNeeded()
+        .accounts({
+          group: fixture.group,
+          escrow,
+          mint,
+          escrowTokenAccount: vault,
+          owner: owner.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: anchor.web3.SystemProgram.programId,
+        } as any)
+        .rpc();
+    }
+
+    const data = await program.account.escrowAccount.fetch(escrow);
+    assert.ok(data.mint.equals(mint));
+    const group = await program.account.groupAccount.fetch(fixture.group);
+    assert.equal(group.escrowCount, 2);
+  });
+});
+
+describe('claim_expired_refund', () => {
+  // Sent by the provider wallet: anyone may return a member's funds to them
+  const claim = (fixture: GroupFixture, member: MemberFixture) =>
+    program.methods
+      .claimExpiredRefund()
+      .accounts({
+        group: fixture.group,
+        member: member.account,
+        escrow: fixture.escrow,
+        mint: fixture.mint,
+        memberTokenAccount: member.tokenAccount,
+        escrowTokenAccount: fixture.vault,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      } as any)
+      .rpc();
+
+  it('Refunds every depositor once an underfunded group expires', async () => {
+    const fixture = await setupGroup({ totalCost: 300, due: fromNow(5) });
+    const members = [
+      await addMember(fixture),
+      await addMember(fixture),
+      await addMember(fixture),
+    ];
+    await deposit(fixture, members[0], 100);
+    await deposit(fixture, members[1], 60);
+
+    await expectError(claim(fixture, members[0]), 'TooEarly');
+    await sleepUntil(fixture.due);
+
+    await claim(fixture, members[0]);
+    await claim(fixture, members[1]);
+    await expectError(claim(fixture, members[2]), 'NothingToRefund');
+
+    for (const m of members) {
+      assert.equal(await tokenBalance(m.tokenAccount), 1000);
+    }
+    const escrow = await program.account.escrowAccount.fetch(fixture.escrow);
+    assert.equal(escrow.totalHeld.toNumber(), 0);
+  });
+
+  it('Rejects a claim on a fully funded group', async () => {
+    const fixture = await setupGroup({ totalCost: 100, due: fromNow(5) });
+    const member = await addMember(fixture);
+    await deposit(fixture, member, 100);
+
+    await sleepUntil(fixture.due);
+    await expectError(claim(fixture, member), 'GroupFunded');
+  });
+});
+
+describe('leave_group', () => {
+  it('Refunds a leaving member and returns their account rent', async () => {
+    const fixture = await setupGroup({ totalCost: 200, due: fromNow(20) });
+    const stayer = await addMember(fixture);
+    const leaver = await addMember(fixture);
+    await deposit(fixture, leaver, 100);
+
+    const lamportsBefore = await provider.connection.getBalance(
+      leaver.authority.publicKey,
+    );
+    await leave(fixture, leaver);
+
+    assert.isNull(
+      await program.account.memberAccount.fetchNullable(leaver.account),
+    );
+    const lamportsAfter = await provider.connection.getBalance(
+      leaver.authority.publicKey,
+    );
+    assert.isAbove(lamportsAfter, lamportsBefore);
+    assert.equal(await tokenBalance(leaver.tokenAccount), 1000);
+
+    const group = await program.account.groupAccount.fetch(fixture.group);
+    assert.equal(group.memberCount, 1);
+    assert.equal(group.totalWeight, 1);
+    assert.equal(group.members.length, 1);
+    assert.ok(group.members[0].equals(stayer.authority.publicKey));
+    const escrow = await program.account.escrowAccount.fetch(fixture.escrow);
+    assert.equal(escrow.totalHeld.toNumber(), 0);
+  });
+
+  it('Rejects leaving once the group is due', async () => {
+    const fixture = await setupGroup({ totalCost: 100, due: fromNow(5) });
+    const member = await addMember(fixture);
+
+    await sleepUntil(fixture.due);
+    await expectError(leave(fixture, member), 'TooLate');
+  });
+});
+
+describe('kick_member', () => {
+  const kick = (fixture: GroupFixture, member: MemberFixture) =>
+    program.methods
+      .kickMember()
+      .accounts({
+        group: fixture.group,
+        owner: owner.publicKey,
+        member: member.account,
+        memberWallet: member.authority.publicKey,
+        escrow: fixture.escrow,
+        mint: fixture.mint,
+        memberTokenAccount: member.tokenAccount,
+        escrowTokenAccount: fixture.vault,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      } as any)
+      .rpc();
+
+  it('Refunds a kicked member and drops them from the count', async () => {
+    const fixture = await setupGroup({ totalCost: 200, due: fromNow(20) });
+    const stayer = await addMember(fixture);
+    const kicked = await addMember(fixture);
+    await deposit(fixture, kicked, 100);
+
+    const lamportsBefore = await provider.connection.getBalance(
+      kicked.authority.publicKey,
+    );
+    await kick(fixture, kicked);
+
+    assert.isNull(
+      await program.account.memberAccount.fetchNullable(kicked.account),
+    );
+    const lamportsAfter = await provider.connection.getBalance(
+      kicked.authority.publicKey,
+    );
+    assert.isAbove(lamportsAfter, lamportsBefore);
+    assert.equal(await tokenBalance(kicked.tokenAccount), 1000);
+
+    const group = await program.account.groupAccount.fetch(fixture.group);
+    assert.equal(group.memberCount, 1);
+    assert.equal(group.paidCount, 0);
+    assert.ok(group.members[0].equals(stayer.authority.publicKey));
+    const escrow = await program.account.escrowAccount.fetch(fixture.escrow);
+    assert.equal(escrow.totalHeld.toNumber(), 0);
+  });
+
+  it('Rejects a kick by anyone but the owner', async () => {
+    const fixture = await setupGroup({ totalCost: 100, due: fromNow(20) });
+    const member = await addMember(fixture);
+    const stranger = anchor.web3.Keypair.generate();
+
+    await expectError(
+      program.methods
+        .kickMember()
+        .accounts({
+          group: fixture.group,
+          owner: stranger.publicKey,
+          member: member.account,
+          memberWallet: member.authority.publicKey,
+          escrow: fixture.escrow,
+          mint: fixture.mint,
+          memberTokenAccount: member.tokenAccount,
+          escrowTokenAccount: fixture.vault,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        } as any)
+        .signers([stranger])
+        .rpc(),
+      'ConstraintHasOne',
+    );
+  });
+});
+
+describe('streamed payouts', () => {
+  let fixture: GroupFixture;
+  let members: MemberFixture[];
+
+  before(async () => {
+    fixture = await setupGroup({
+      totalCost: 300,
+      due: fromNow(8),
+      streamPayouts: true,
+    });
+    members = [];
+    for (let i = 0; i < 3; i++) members.push(await addMember(fixture));
+  });
+
+  it('Rejects streaming alongside a fee', async () => {
+    await expectError(
+      setupGroup({ streamPayouts: true, feeBps: 100 }),
+      'InvalidStreamConfig',
+    );
+  });
+
+  it('Forwards each deposit to the owner as it arrives', async () => {
+    await expectError(
+      deposit(fixture, members[0], 100),
+      'WrongPayoutRecipient',
+    );
+    for (const [i, m] of members.entries()) {
+      await deposit(fixture, m, 100, fixture.ownerTokenAccount);
+      assert.equal(
+        await tokenBalance(fixture.ownerTokenAccount),
+        100 * (i + 1),
+      );
+      assert.equal(await tokenBalance(fixture.vault), 0);
+    }
 
-  const groupName = 'Test Group';
-  const totalCost = new anchor.BN(1000);
-  const subscriptionDue = new anchor.BN(Math.floor(Date.now() / 1000) + 2); // 2 seconds in future
+    const group = await program.account.groupAccount.fetch(fixture.group);
+    assert.equal(group.streamedTotal.toNumber(), 300);
+  });
 
-  before(async () => {
-    // Create token mint
-    mint = await createMint(
+  it('Rejects refunds of streamed deposits', async () => {
+    await expectError(refund(fixture, members[0]), 'FundsStreamed');
+  });
+
+  it('Closes the cycle at payout without moving funds', async () => {
+    await sleepUntil(fixture.due);
+    await payout(fixture);
+
+    const group = await program.account.groupAccount.fetch(fixture.group);
+    assert.equal(group.isActive, false);
+    assert.equal(await tokenBalance(fixture.ownerTokenAccount), 300);
+  });
+});
+
+describe('set_payout_account', () => {
+  it('Redirects the payout to a fresh token account', async () => {
+    const fixture = await setupGroup({ totalCost: 100, due: fromNow(5) });
+    const member = await addMember(fixture);
+    await deposit(fixture, member, 100);
+
+    const fresh = await createAccount(
       provider.connection,
       owner.payer,
+      fixture.mint,
       owner.publicKey,
-      null,
-      0, // decimals
+      anchor.web3.Keypair.generate(),
     );
+    await program.methods
+      .setPayoutAccount(fresh)
+      .accounts({
+        group: fixture.group,
+        escrow: fixture.escrow,
+        newPayoutAccount: fresh,
+        owner: owner.publicKey,
+      } as any)
+      .rpc();
 
-    // Create token accounts
-    memberTokenAccount = await createAccount(
-      provider.connection,
-      owner.payer,
-      mint,
-      member.publicKey,
+    await sleepUntil(fixture.due);
+    await expectError(payout(fixture), 'WrongPayoutAccount');
+    await payout({ ...fixture, ownerTokenAccount: fresh });
+
+    assert.equal(await tokenBalance(fresh), 100);
+    assert.equal(await tokenBalance(fixture.ownerTokenAccount), 0);
+  });
+
+  it('Rejects an account the payout recipient does not own', async () => {
+    const fixture = await setupGroup();
+    await expectError(
+      program.methods
+        .setPayoutAccount(fixture.feeTokenAccount)
+        .accounts({
+          group: fixture.group,
+          escrow: fixture.escrow,
+          newPayoutAccount: fixture.feeTokenAccount,
+          owner: owner.publicKey,
+        } as any)
+        .rpc(),
+      'WrongPayoutRecipient',
     );
-    escrowTokenAccount = await createAccount(
+  });
+});
+
+describe('payout recipient', () => {
+  it('Pays a recipient other than the owner', async () => {
+    const merchant = anchor.web3.Keypair.generate().publicKey;
+    const fixture = await setupGroup({
+      totalCost: 300,
+      due: fromNow(5),
+      payoutRecipient: merchant,
+    });
+    const merchantTokenAccount = await createAccount(
       provider.connection,
       owner.payer,
-      mint,
-      owner.publicKey,
+      fixture.mint,
+      merchant,
     );
-    ownerTokenAccount = await createAccount(
-      provider.connection,
-      owner.payer,
-      mint,
-      owner.publicKey,
+    const member = await addMember(fixture);
+    await deposit(fixture, member, 300);
+
+    await sleepUntil(fixture.due);
+    await expectError(payout(fixture), 'WrongPayoutRecipient');
+    await payout({ ...fixture, ownerTokenAccount: merchantTokenAccount });
+
+    assert.equal(await tokenBalance(merchantTokenAccount), 300);
+    assert.equal(await tokenBalance(fixture.ownerTokenAccount), 0);
+  });
+});
+
+describe('overpayment credit', () => {
+  it('Carries an overpayment over to the next cycle', async () => {
+    const fixture = await setupGroup({
+      totalCost: 100,
+      due: fromNow(5),
+      allowCredit: true,
+    });
+    const member = await addMember(fixture);
+    await deposit(fixture, member, 130);
+
+    let data = await program.account.memberAccount.fetch(member.account);
+    assert.equal(data.hasPaid, true);
+    assert.equal(data.credit.toNumber(), 30);
+    let escrow = await program.account.escrowAccount.fetch(fixture.escrow);
+    assert.equal(escrow.totalHeld.toNumber(), 100);
+    assert.equal(escrow.creditHeld.toNumber(), 30);
+
+    await sleepUntil(fixture.due);
+    await payout(fixture);
+    assert.equal(await tokenBalance(fixture.ownerTokenAccount), 100);
+    assert.equal(await tokenBalance(fixture.vault), 30);
+
+    await program.methods
+      .renewGroup(fromNow(60))
+      .accounts({ group: fixture.group, owner: owner.publicKey } as any)
+      .remainingAccounts([
+        { pubkey: member.account, isWritable: true, isSigner: false },
+      ])
+      .rpc();
+
+    // The credit is spent first, leaving 70 to pay in the second cycle
+    assert.equal(await returnedU64(await deposit(fixture, member, 20)), 50);
+    await deposit(fixture, member, 50);
+    data = await program.account.memberAccount.fetch(member.account);
+    assert.equal(data.hasPaid, true);
+    assert.equal(data.credit.toNumber(), 0);
+    escrow = await program.account.escrowAccount.fetch(fixture.escrow);
+    assert.equal(escrow.totalHeld.toNumber(), 100);
+    assert.equal(escrow.creditHeld.toNumber(), 0);
+    assert.equal(await tokenBalance(member.tokenAccount), 1000 - 130 - 70);
+  });
+
+  it('Returns credit from a paid-out group so it can be closed', async () => {
+    const fixture = await setupGroup({
+      totalCost: 100,
+      due: fromNow(5),
+      allowCredit: true,
+    });
+    const member = await addMember(fixture);
+    await deposit(fixture, member, 130);
+    await sleepUntil(fixture.due);
+    await payout(fixture);
+
+    await refund(fixture, member);
+    const data = await program.account.memberAccount.fetch(member.account);
+    assert.equal(data.credit.toNumber(), 0);
+    const escrow = await program.account.escrowAccount.fetch(fixture.escrow);
+    assert.equal(escrow.creditHeld.toNumber(), 0);
+    assert.equal(await tokenBalance(member.tokenAccount), 1000 - 100);
+    assert.equal(await tokenBalance(fixture.vault), 0);
+    await expectError(refund(fixture, member), 'NothingToRefund');
+
+    await program.methods
+      .closeGroup()
+      .accounts({
+        group: fixture.group,
+        escrow: fixture.escrow,
+        vault: fixture.vault,
+        owner: owner.publicKey,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      } as any)
+      .rpc();
+    assert.isNull(await provider.connection.getAccountInfo(fixture.group));
+  });
+
+  it('Still rejects overpayments in groups without credit', async () => {
+    const fixture = await setupGroup({ totalCost: 100 });
+    const member = await addMember(fixture);
+    await expectError(deposit(fixture, member, 130), 'ExceedsShare');
+  });
+});
+
+describe('restricted payouts', () => {
+  it('Only lets the owner settle a restricted group', async () => {
+    const fixture = await setupGroup({
+      totalCost: 100,
+      due: fromNow(5),
+      restrictPayout: true,
+    });
+    const member = await addMember(fixture);
+    await deposit(fixture, member, 100);
+    await sleepUntil(fixture.due);
+
+    await expectError(payout(fixture), 'Unauthorized');
+    await payout(fixture, [], owner.publicKey);
+    assert.equal(await tokenBalance(fixture.ownerTokenAccount), 100);
+  });
+
+  it('Lets anyone settle an unrestricted group', async () => {
+    const fixture = await setupGroup({ totalCost: 100, due: fromNow(5) });
+    const member = await addMember(fixture);
+    await deposit(fixture, member, 100);
+    await sleepUntil(fixture.due);
+
+    await payout(fixture);
+    assert.equal(await tokenBalance(fixture.ownerTokenAccount), 100);
+  });
+});
+
+describe('settlement delay', () => {
+  it('Holds the payout until the delay after the due time', async () => {
+    const fixture = await setupGroup({
+      totalCost: 100,
+      due: fromNow(5),
+      settlementDelay: 4,
+    });
+    await deposit(fixture, await addMember(fixture), 100);
+
+    await sleepUntil(fixture.due);
+    await expectError(payout(fixture), 'TooEarly');
+    await sleepUntil(fixture.due.addn(4));
+    await payout(fixture);
+    assert.equal(await tokenBalance(fixture.ownerTokenAccount), 100);
+  });
+
+  it('Rejects a negative delay', async () => {
+    await expectError(
+      setupGroup({ settlementDelay: -1 }),
+      'InvalidSettlementDelay',
+    );
+  });
+});
+
+describe('payout approvals', () => {
+  const approvers = [...Array(3).keys()].map(() =>
+    anchor.web3.Keypair.generate(),
+  );
+
+  const approve = (fixture: GroupFixture, approver: anchor.web3.Keypair) =>
+    program.methods
+      .approvePayout()
+      .accounts({
+        group: fixture.group,
+        escrow: fixture.escrow,
+        approver: approver.publicKey,
+      } as any)
+      .signers([approver])
+      .rpc();
+
+  const setupApproved = (totalCost = 1000) =>
+    setupGroup({
+      totalCost,
+      due: fromNow(5),
+      approvers: approvers.map((approver) => approver.publicKey),
+      threshold: 2,
+      approvalAbove: 500,
+    });
+
+  before(async () => {
+    for (const approver of approvers) {
+      await airdrop(approver.publicKey);
+    }
+  });
+
+  it('Blocks a large payout until two of three approve', async () => {
+    const fixture = await setupApproved();
+    await deposit(fixture, await addMember(fixture), 1000);
+    await sleepUntil(fixture.due);
+
+    await expectError(payout(fixture), 'ApprovalsMissing');
+    await approve(fixture, approvers[0]);
+    // Approving twice still counts once
+    await approve(fixture, approvers[0]);
+    await expectError(payout(fixture), 'ApprovalsMissing');
+
+    await approve(fixture, approvers[2]);
+    await payout(fixture);
+    assert.equal(await tokenBalance(fixture.ownerTokenAccount), 1000);
+    const escrow = await program.account.escrowAccount.fetch(fixture.escrow);
+    assert.equal(escrow.approvals, 0);
+  });
+
+  it('Pays out below the threshold without approvals', async () => {
+    const fixture = await setupApproved(400);
+    await deposit(fixture, await addMember(fixture), 400);
+    await sleepUntil(fixture.due);
+
+    await payout(fixture);
+    assert.equal(await tokenBalance(fixture.ownerTokenAccount), 400);
+  });
+
+  it('Rejects approvals from outside the approver list', async () => {
+    const fixture = await setupApproved();
+    const stranger = anchor.web3.Keypair.generate();
+    await airdrop(stranger.publicKey);
+    await expectError(approve(fixture, stranger), 'NotAnApprover');
+  });
+
+  it('Rejects a threshold above the approver count', async () => {
+    await expectError(
+      setupGroup({
+        approvers: approvers.map((approver) => approver.publicKey),
+        threshold: 4,
+      }),
+      'InvalidApprovers',
     );
+  });
+});
+
+describe('membership lock', () => {
+  it('Rejects invites once a deposit has been made', async () => {
+    const fixture = await setupGroup({ totalCost: 200 });
+    const member = await addMember(fixture);
+    await deposit(fixture, member, 50);
+
+    await expectError(addMember(fixture), 'MembershipLocked');
+    const group = await program.account.groupAccount.fetch(fixture.group);
+    assert.equal(group.memberCount, 1);
+  });
+
+  it('Reopens invites once deposits are refunded', async () => {
+    const fixture = await setupGroup({ totalCost: 200 });
+    const member = await addMember(fixture);
+    await deposit(fixture, member, 50);
+    await refund(fixture, member);
+
+    await addMember(fixture);
+  });
+
+  it('Accepts late joiners when the group allows them', async () => {
+    const fixture = await setupGroup({ totalCost: 200, allowLateJoin: true });
+    const member = await addMember(fixture);
+    await deposit(fixture, member, 50);
+
+    await addMember(fixture);
+    const group = await program.account.groupAccount.fetch(fixture.group);
+    assert.equal(group.memberCount, 2);
+  });
+
+  it("Keeps an earlier member's share when someone joins late", async () => {
+    const fixture = await setupGroup({
+      totalCost: 300,
+      due: fromNow(15),
+      allowLateJoin: true,
+    });
+    const first = await addMember(fixture);
+    const second = await addMember(fixture);
+    await deposit(fixture, first, 50);
+    let data = await program.account.memberAccount.fetch(first.account);
+    assert.equal(data.owed.toNumber(), 150);
+
+    const late = await addMember(fixture);
+    data = await program.account.memberAccount.fetch(first.account);
+    assert.equal(data.owed.toNumber(), 150);
+    // The late joiner's share is pinned on arrival, splitting what the first
+    // member's pinned share leaves of the cost with the second member
+    const joined = await program.account.memberAccount.fetch(late.account);
+    assert.equal(joined.owed.toNumber(), 75);
+
+    await expectError(deposit(fixture, first, 101), 'ExceedsShare');
+    await deposit(fixture, first, 100);
+    data = await program.account.memberAccount.fetch(first.account);
+    assert.equal(data.hasPaid, true);
+    await expectError(deposit(fixture, second, 76), 'ExceedsShare');
+    await deposit(fixture, second, 75);
+    await deposit(fixture, late, 75);
+
+    const group = await program.account.groupAccount.fetch(fixture.group);
+    assert.equal(group.paidCount, 3);
+    assert.equal(group.totalHeld.toNumber(), 300);
+    await sleepUntil(fixture.due);
+    await payout(fixture);
+    assert.equal(await tokenBalance(fixture.ownerTokenAccount), 300);
+  });
+});
+
+describe('minimum deposit', () => {
+  it('Rejects a deposit below the minimum', async () => {
+    const fixture = await setupGroup({ totalCost: 100, minDeposit: 30 });
+    const member = await addMember(fixture);
+    await expectError(deposit(fixture, member, 29), 'DepositTooSmall');
+    await deposit(fixture, member, 80);
+
+    // The last installment may be smaller if it completes the share
+    await deposit(fixture, member, 20);
+    const data = await program.account.memberAccount.fetch(member.account);
+    assert.equal(data.hasPaid, true);
+  });
+});
+
+describe('per-member cap', () => {
+  it('Accepts deposits up to the cap and rejects any more', async () => {
+    const fixture = await setupGroup({
+      totalCost: 1000,
+      allowPartial: true,
+      maxPerMember: 400,
+    });
+    const member = await addMember(fixture);
+    await deposit(fixture, member, 300);
+    await deposit(fixture, member, 100);
+    await expectError(deposit(fixture, member, 1), 'PerMemberCapExceeded');
+
+    const data = await program.account.memberAccount.fetch(member.account);
+    assert.equal(data.contributed.toNumber(), 400);
+  });
+
+  it('Leaves contributions uncapped when the cap is zero', async () => {
+    const fixture = await setupGroup({ totalCost: 1000 });
+    const member = await addMember(fixture);
+    await deposit(fixture, member, 1000);
+
+    const data = await program.account.memberAccount.fetch(member.account);
+    assert.equal(data.hasPaid, true);
+  });
+});
+
+describe('escrow capacity', () => {
+  it('Rejects a deposit that would collect more than the cost', async () => {
+    const fixture = await setupGroup({ totalCost: 200, allowLateJoin: true });
+    const early = await addMember(fixture);
+    await deposit(fixture, early, 200);
+
+    // A late invite halves the share, but the escrow already holds the cost
+    const late = await addMember(fixture);
+    await expectError(deposit(fixture, late, 100), 'ExceedsCost');
+
+    const escrow = await program.account.escrowAccount.fetch(fixture.escrow);
+    assert.equal(escrow.totalHeld.toNumber(), 200);
+  });
+
+  it('Caps the cost across every mint of the group', async () => {
+    const usdc = await setupGroup({ totalCost: 200, allowLateJoin: true });
+    const usdt = await addEscrow(usdc);
+    await deposit(usdc, await addMember(usdc), 200);
+
+    // The second mint's escrow is empty, but the group already holds the cost
+    const late = await addMember(usdt);
+    await expectError(deposit(usdt, late, 100), 'ExceedsCost');
+
+    const group = await program.account.groupAccount.fetch(usdc.group);
+    assert.equal(group.totalHeld.toNumber(), 200);
+  });
+});
+
+describe('overflow', () => {
+  it('Rejects a deposit that would overflow instead of panicking', async () => {
+    const fixture = await setupGroup();
+    const member = await addMember(fixture);
+    await deposit(fixture, member, 1);
 
-    // Mint some tokens to member
+    const u64Max = new anchor.BN(2).pow(new anchor.BN(64)).subn(1);
+    await expectError(deposit(fixture, member, u64Max), 'Overflow');
+
+    const escrow = await program.account.escrowAccount.fetch(fixture.escrow);
+    assert.equal(escrow.totalHeld.toNumber(), 1);
+  });
+});
+
+describe('weighted shares', () => {
+  let fixture: GroupFixture;
+  let ownerMember: MemberFixture;
+  let single: MemberFixture;
+  let double: MemberFixture;
+
+  before(async () => {
+    // 1001 over four weight units leaves a remainder of 1 for the owner,
+    // who is invited first
+    fixture = await setupGroup({ totalCost: 1001 });
     await mintTo(
       provider.connection,
       owner.payer,
-      mint,
-      memberTokenAccount,
+      fixture.mint,
+      fixture.ownerTokenAccount,
       owner.publicKey,
       1000,
     );
+    await invite(fixture, owner.payer, 1);
+    ownerMember = {
+      authority: owner.payer,
+      account: memberPda(fixture, owner.payer),
+      tokenAccount: fixture.ownerTokenAccount,
+    };
+    single = await addMember(fixture, 1000, 1);
+    double = await addMember(fixture, 1000, 2);
+  });
+
+  it('Tracks the total weight', async () => {
+    const group = await program.account.groupAccount.fetch(fixture.group);
+    assert.equal(group.totalWeight, 4);
+    const data = await program.account.memberAccount.fetch(double.account);
+    assert.equal(data.weight, 2);
+  });
+
+  it('Rejects a zero weight', async () => {
+    const wallet = anchor.web3.Keypair.generate();
+    await airdrop(wallet.publicKey);
+    await expectError(invite(fixture, wallet, 0), 'InvalidWeight');
+  });
+
+  it('Charges each member in proportion to their weight', async () => {
+    await expectError(deposit(fixture, double, 501), 'ExceedsShare');
+    await deposit(fixture, double, 500);
+    await deposit(fixture, single, 250);
+    // The first member invited absorbs the rounding remainder
+    await expectError(deposit(fixture, ownerMember, 252), 'ExceedsShare');
+    await deposit(fixture, ownerMember, 251);
+
+    for (const m of [ownerMember, single, double]) {
+      const data = await program.account.memberAccount.fetch(m.account);
+      assert.equal(data.hasPaid, true);
+    }
+    const escrow = await program.account.escrowAccount.fetch(fixture.escrow);
+    assert.equal(escrow.totalHeld.toNumber(), 1001);
+  });
+});
 
-    // Generate PDAs/accounts
-    groupAccount = anchor.web3.Keypair.generate();
-    memberAccount = anchor.web3.Keypair.generate();
-    escrowAccount = anchor.web3.Keypair.generate();
+describe('weighted removal', () => {
+  it('Recomputes shares from the remaining weight', async () => {
+    const fixture = await setupGroup({ totalCost: 1000, due: fromNow(30) });
+    const first = await addMember(fixture, 1000, 1);
+    const heavy = await addMember(fixture, 1000, 2);
+    const last = await addMember(fixture, 1000, 1);
+    await expectError(deposit(fixture, first, 251), 'ExceedsShare');
 
-    // Fund the member so they can pay rent
-    await provider.connection.requestAirdrop(
-      member.publicKey,
-      anchor.web3.LAMPORTS_PER_SOL,
+    await leave(fixture, heavy);
+    const group = await program.account.groupAccount.fetch(fixture.group);
+    assert.equal(group.memberCount, 2);
+    assert.equal(group.totalWeight, 2);
+
+    // Half each once the double share is gone
+    await expectError(deposit(fixture, first, 501), 'ExceedsShare');
+    await deposit(fixture, first, 500);
+    await deposit(fixture, last, 500);
+    const escrow = await program.account.escrowAccount.fetch(fixture.escrow);
+    assert.equal(escrow.totalHeld.toNumber(), 1000);
+  });
+});
+
+describe('ownership transfer', () => {
+  let fixture: GroupFixture;
+  const newOwner = anchor.web3.Keypair.generate();
+
+  const accept = (signer: anchor.web3.Keypair) =>
+    program.methods
+      .acceptOwnership()
+      .accounts({ group: fixture.group, newOwner: signer.publicKey } as any)
+      .signers([signer])
+      .rpc();
+
+  before(async () => {
+    fixture = await setupGroup();
+  });
+
+  it('Rejects a nomination by anyone but the owner', async () => {
+    await expectError(
+      program.methods
+        .transferOwnership(newOwner.publicKey)
+        .accounts({ group: fixture.group, owner: newOwner.publicKey } as any)
+        .signers([newOwner])
+        .rpc(),
+      'ConstraintHasOne',
     );
   });
 
-  it('Creates a group', async () => {
+  it('Rejects acceptance by a wallet that was not nominated', async () => {
     await program.methods
-      .createGroup(groupName, totalCost, subscriptionDue)
-      .accounts({
-        group: groupAccount.publicKey,
-        owner: owner.publicKey,
-        systemProgram: anchor.web3.SystemProgram.programId,
-      } as any)
-      .signers([groupAccount])
+      .transferOwnership(newOwner.publicKey)
+      .accounts({ group: fixture.group, owner: owner.publicKey } as any)
       .rpc();
 
-    const group = await program.account.groupAccount.fetch(
-      groupAccount.publicKey,
+    await expectError(
+      accept(anchor.web3.Keypair.generate()),
+      'NotPendingOwner',
     );
-    assert.equal(group.groupName, groupName);
-    assert.ok(group.isActive);
   });
 
-  it('Invites a member', async () => {
-    await program.methods
-      .inviteMember()
+  it('Transfers ownership once the nominee accepts', async () => {
+    await accept(newOwner);
+
+    const group = await program.account.groupAccount.fetch(fixture.group);
+    assert.ok(group.owner.equals(newOwner.publicKey));
+    assert.isNull(group.pendingOwner);
+  });
+});
+
+describe('pause', () => {
+  let fixture: GroupFixture;
+  let members: MemberFixture[];
+
+  const setPaused = (paused: boolean) =>
+    program.methods
+      .setPaused(paused)
+      .accounts({ group: fixture.group, owner: owner.publicKey } as any)
+      .rpc();
+
+  before(async () => {
+    fixture = await setupGroup({ totalCost: 400 });
+    members = [await addMember(fixture), await addMember(fixture)];
+    await deposit(fixture, members[0], 200);
+  });
+
+  it('Blocks deposits while paused but still allows refunds', async () => {
+    await setPaused(true);
+    await expectError(deposit(fixture, members[1], 200), 'GroupPaused');
+
+    await refund(fixture, members[0]);
+    assert.equal(await tokenBalance(members[0].tokenAccount), 1000);
+  });
+
+  it('Accepts deposits again once unpaused', async () => {
+    await setPaused(false);
+    await deposit(fixture, members[1], 200);
+
+    const data = await program.account.memberAccount.fetch(members[1].account);
+    assert.equal(data.hasPaid, true);
+  });
+});
+
+// The config is a program-wide singleton, so these run after the other group
+// tests, except yield's, and put the defaults back afterwards
+describe('program config', () => {
+  const [configPda] = anchor.web3.PublicKey.findProgramAddressSync(
+    [Buffer.from('config')],
+    program.programId,
+  );
+
+  const updateConfig = (
+    defaultFeeBps: number,
+    maxTotalCost: number,
+    admin = owner.payer,
+    antiSelfSplit = false,
+  ) =>
+    program.methods
+      .updateConfig({
+        defaultFeeBps,
+        maxTotalCost: new anchor.BN(maxTotalCost),
+        antiSelfSplit,
+      })
+      .accounts({ config: configPda, admin: admin.publicKey } as any)
+      .signers([admin])
+      .rpc();
+
+  const setProgramPaused = (paused: boolean) =>
+    program.methods
+      .setProgramPaused(paused)
+      .accounts({ config: configPda, admin: owner.publicKey } as any)
+      .rpc();
+
+  after(async () => {
+    await updateConfig(0, 0);
+    await setProgramPaused(false);
+  });
+
+  it('Initializes the config once, from the upgrade authority', async () => {
+    const [programData] = anchor.web3.PublicKey.findProgramAddressSync(
+      [program.programId.toBuffer()],
+      anchor.web3.BPF_LOADER_UPGRADEABLE_PROGRAM_ID,
+    );
+    const initialize = (admin = owner.payer) =>
+      program.methods
+        .initializeConfig({
+          defaultFeeBps: 0,
+          maxTotalCost: new anchor.BN(0),
+          antiSelfSplit: false,
+        })
+        .accounts({
+          config: configPda,
+          admin: admin.publicKey,
+          program: program.programId,
+          programData,
+          systemProgram: anchor.web3.SystemProgram.programId,
+        } as any)
+        .signers([admin])
+        .rpc();
+
+    // Nobody but the deployer can claim the admin role
+    const stranger = anchor.web3.Keypair.generate();
+    await airdrop(stranger.publicKey);
+    await expectError(initialize(stranger), 'Unauthorized');
+
+    await initialize();
+    const config = await program.account.programConfig.fetch(configPda);
+    assert.ok(config.admin.equals(owner.publicKey));
+    await expectError(initialize(), 'already in use');
+  });
+
+  it('Only lets the admin update it', async () => {
+    const stranger = anchor.web3.Keypair.generate();
+    await expectError(updateConfig(250, 0, stranger), 'Unauthorized');
+  });
+
+  it('Gives groups the default fee and enforces the cost cap', async () => {
+    await updateConfig(250, 5000);
+
+    const inherited = await setupGroup({ feeBps: null });
+    let group = await program.account.groupAccount.fetch(inherited.group);
+    assert.equal(group.feeBps, 250);
+    const explicit = await setupGroup({ feeBps: 100 });
+    group = await program.account.groupAccount.fetch(explicit.group);
+    assert.equal(group.feeBps, 100);
+
+    await expectError(setupGroup({ totalCost: 5001 }), 'CostTooHigh');
+  });
+
+  it('Rejects self-splits under the anti-self-split policy', async () => {
+    await updateConfig(0, 0, owner.payer, true);
+    await expectError(setupGroup({ minMembers: 1 }), 'SelfSplit');
+
+    const fixture = await setupGroup({
+      totalCost: 200,
+      due: fromNow(5),
+      minMembers: 2,
+      allowPartial: true,
+    });
+    const payer = await addMember(fixture);
+    await addMember(fixture);
+    await deposit(fixture, payer, 100);
+    await sleepUntil(fixture.due);
+    // A lone depositor can't be paid out, even in a partial-payout group
+    await expectError(payout(fixture), 'SelfSplit');
+
+    const group = await program.account.groupAccount.fetch(fixture.group);
+    assert.equal(group.antiSelfSplit, true);
+    assert.equal(group.paidCount, 1);
+  });
+
+  it('Halts deposits while paused but still lets members refund', async () => {
+    const fixture = await setupGroup({ totalCost: 200 });
+    const member = await addMember(fixture);
+    await deposit(fixture, member, 50);
+
+    await setProgramPaused(true);
+    await expectError(deposit(fixture, member, 50), 'ProgramPaused');
+    await expectError(addMember(fixture), 'ProgramPaused');
+    await expectError(setupGroup(), 'ProgramPaused');
+    await refund(fixture, member);
+    assert.equal(await tokenBalance(member.tokenAccount), 1000);
+
+    await setProgramPaused(false);
+    await deposit(fixture, member, 50);
+  });
+});
+
+// Yield programs come from the config's allowlist, so these run after the
+// config has been initialized
+describe('yield', () => {
+  const mockYield = anchor.workspace.MockYield as Program<MockYield>;
+  const [configPda] = anchor.web3.PublicKey.findProgramAddressSync(
+    [Buffer.from('config')],
+    program.programId,
+  );
+
+  const setYieldPrograms = (programs: anchor.web3.PublicKey[]) =>
+    program.methods
+      .setYieldPrograms(programs)
+      .accounts({ config: configPda, admin: owner.publicKey } as any)
+      .rpc();
+
+  before(async () => {
+    await setYieldPrograms([mockYield.programId]);
+  });
+
+  after(async () => {
+    await setYieldPrograms([]);
+  });
+
+  const poolPda = (depositor: anchor.web3.PublicKey) =>
+    anchor.web3.PublicKey.findProgramAddressSync(
+      [Buffer.from('pool'), depositor.toBuffer()],
+      mockYield.programId,
+    )[0];
+
+  const meta = (pubkey: anchor.web3.PublicKey, isWritable: boolean) => ({
+    pubkey,
+    isWritable,
+    isSigner: false,
+  });
+
+  const yieldAccounts = (fixture: GroupFixture) => ({
+    group: fixture.group,
+    owner: owner.publicKey,
+    escrow: fixture.escrow,
+    escrowTokenAccount: fixture.vault,
+    yieldProgram: mockYield.programId,
+  });
+
+  // A yield-enabled group with one member's 500 deposited and a pool to lend to
+  const setupLending = async () => {
+    const fixture = await setupGroup({
+      totalCost: 500,
+      due: fromNow(30),
+      yieldEnabled: true,
+      yieldProgram: mockYield.programId,
+    });
+    const member = await addMember(fixture);
+    await deposit(fixture, member, 500);
+    const pool = poolPda(fixture.escrow);
+    await mockYield.methods
+      .initPool()
       .accounts({
-        group: groupAccount.publicKey,
-        member: memberAccount.publicKey,
-        memberAuthority: member.publicKey,
+        depositor: fixture.escrow,
+        mint: fixture.mint,
+        pool,
+        payer: owner.publicKey,
+        tokenProgram: TOKEN_PROGRAM_ID,
         systemProgram: anchor.web3.SystemProgram.programId,
       } as any)
-      .signers([member, memberAccount])
       .rpc();
+    return { fixture, member, pool };
+  };
 
-    const memberData = await program.account.memberAccount.fetch(
-      memberAccount.publicKey,
+  const lend = (
+    fixture: GroupFixture,
+    pool: anchor.web3.PublicKey,
+    amount: anchor.BN,
+  ) =>
+    program.methods
+      .depositToYield(
+        amount,
+        mockYield.coder.instruction.encode('deposit', { amount }),
+      )
+      .accounts(yieldAccounts(fixture) as any)
+      .remainingAccounts([
+        meta(fixture.escrow, false),
+        meta(fixture.vault, true),
+        meta(pool, true),
+        meta(TOKEN_PROGRAM_ID, false),
+      ])
+      .rpc();
+
+  // The yield program's withdraw instruction, as passed to withdraw and recall
+  const withdrawData = () => mockYield.coder.instruction.encode('withdraw', {});
+  const withdrawAccounts = (
+    fixture: GroupFixture,
+    pool: anchor.web3.PublicKey,
+  ) => [
+    meta(fixture.escrow, false),
+    meta(pool, true),
+    meta(fixture.vault, true),
+    meta(TOKEN_PROGRAM_ID, false),
+  ];
+
+  it('Round-trips escrowed funds through the yield program', async () => {
+    const { fixture, member, pool } = await setupLending();
+    await lend(fixture, pool, new anchor.BN(400));
+    assert.equal(await tokenBalance(fixture.vault), 100);
+    assert.equal(await tokenBalance(pool), 400);
+    let escrow = await program.account.escrowAccount.fetch(fixture.escrow);
+    assert.equal(escrow.inYield.toNumber(), 400);
+
+    // Funds can't be refunded or paid out while they are lent
+    await expectError(leave(fixture, member), 'YieldOutstanding');
+
+    // Interest accrues in the pool
+    await mintTo(
+      provider.connection,
+      owner.payer,
+      fixture.mint,
+      pool,
+      owner.publicKey,
+      40,
     );
-    assert.equal(memberData.hasPaid, false);
-  });
+    await program.methods
+      .withdrawFromYield(withdrawData())
+      .accounts(yieldAccounts(fixture) as any)
+      .remainingAccounts(withdrawAccounts(fixture, pool))
+      .rpc();
+    assert.equal(await tokenBalance(fixture.vault), 540);
+    escrow = await program.account.escrowAccount.fetch(fixture.escrow);
+    assert.equal(escrow.inYield.toNumber(), 0);
+    assert.equal(escrow.totalHeld.toNumber(), 500);
 
-  it('Member deposits funds', async () => {
+    // The interest is excess the owner can take
     await program.methods
-      .depositFunds(new anchor.BN(500))
+      .withdrawExcess()
       .accounts({
-        group: groupAccount.publicKey,
-        member: memberAccount.publicKey,
-        memberAuthority: member.publicKey,
-        fromTokenAccount: memberTokenAccount,
-        escrowTokenAccount: escrowTokenAccount,
-        escrow: escrowAccount.publicKey,
+        group: fixture.group,
+        escrow: fixture.escrow,
+        mint: fixture.mint,
+        escrowTokenAccount: fixture.vault,
+        ownerTokenAccount: fixture.ownerTokenAccount,
+        owner: owner.publicKey,
         tokenProgram: TOKEN_PROGRAM_ID,
       } as any)
-      .signers([member])
       .rpc();
+    assert.equal(await tokenBalance(fixture.ownerTokenAccount), 40);
+    assert.equal(await tokenBalance(fixture.vault), 500);
+  });
 
-    const memberData = await program.account.memberAccount.fetch(
-      memberAccount.publicKey,
+  it('Rejects the token program as the yield program', async () => {
+    await expectError(
+      setupGroup({ yieldEnabled: true, yieldProgram: TOKEN_PROGRAM_ID }),
+      'InvalidYieldConfig',
     );
-    assert.equal(memberData.hasPaid, true);
   });
 
-  it('Executes payout after due time', async () => {
-    // Wait until subscription_due passes
-    await new Promise((resolve) => setTimeout(resolve, 3000));
+  it('Rejects a yield program off the allowlist', async () => {
+    await setYieldPrograms([]);
+    try {
+      await expectError(
+        setupGroup({ yieldEnabled: true, yieldProgram: mockYield.programId }),
+        'YieldProgramNotAllowed',
+      );
+    } finally {
+      await setYieldPrograms([mockYield.programId]);
+    }
+  });
+
+  it('Lends no more than the escrow holds', async () => {
+    const { fixture, pool } = await setupLending();
+    // Tokens sent straight to the vault aren't the escrow's to lend
+    await mintTo(
+      provider.connection,
+      owner.payer,
+      fixture.mint,
+      fixture.vault,
+      owner.publicKey,
+      100,
+    );
+    await expectError(
+      lend(fixture, pool, new anchor.BN(501)),
+      'ExceedsHoldings',
+    );
+    await lend(fixture, pool, new anchor.BN(500));
+  });
+
+  it('Lets a member recall lent funds to refund', async () => {
+    const { fixture, member, pool } = await setupLending();
+    await lend(fixture, pool, new anchor.BN(500));
 
     await program.methods
-      .executePayout()
+      .recallFromYield(withdrawData())
       .accounts({
-        group: groupAccount.publicKey,
-        escrow: escrowAccount.publicKey,
-        escrowTokenAccount: escrowTokenAccount,
-        ownerTokenAccount: ownerTokenAccount,
-        tokenProgram: TOKEN_PROGRAM_ID,
+        group: fixture.group,
+        member: member.account,
+        memberAuthority: member.authority.publicKey,
+        escrow: fixture.escrow,
+        escrowTokenAccount: fixture.vault,
+        yieldProgram: mockYield.programId,
       } as any)
+      .remainingAccounts(withdrawAccounts(fixture, pool))
+      .signers([member.authority])
       .rpc();
+    const escrow = await program.account.escrowAccount.fetch(fixture.escrow);
+    assert.equal(escrow.inYield.toNumber(), 0);
 
-    const group = await program.account.groupAccount.fetch(
-      groupAccount.publicKey,
-    );
-    assert.equal(group.isActive, false);
+    await leave(fixture, member);
+    assert.equal(await tokenBalance(member.tokenAccount), 1000);
   });
 });